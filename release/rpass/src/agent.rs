@@ -0,0 +1,568 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    constants::RPASS_MASTER_PASSWORD_ENV,
+    data_store::{DataStore, Dirty, PasswordStore, Unlocked},
+    secret::SecretString,
+    storage,
+};
+
+/// Maximum size of a message exchanged with the agent, in bytes.
+const MAX_MESSAGE_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// Maximum time to exchange a message with the agent.
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between two checks of the agent idle timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Possible errors while operating with the agent.
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("An rpass agent is already running for this datastore (socket: {0})")]
+    AlreadyRunning(PathBuf),
+    #[error("The rpass agent cannot be started: {0}")]
+    StartFailed(String),
+    #[error("The rpass agent failed: {0}")]
+    Failed(String),
+    #[error("Datastore has been changed by another command meanwhile, please retry")]
+    Conflict,
+    #[error("Unexpected answer from the rpass agent")]
+    UnexpectedResponse,
+    #[error("The rpass agent is not supported on this platform")]
+    Unsupported,
+}
+
+/// Requests sent to the agent, one per connection.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum Request<'a> {
+    /// Gets the password datasets of the DataStore, and their revision.
+    Get,
+    /// Replaces the password datasets of the DataStore, and saves it.
+    ///
+    /// The revision must be the one given by `Get`, so that no change is overwritten.
+    Put {
+        revision: u64,
+        data: Cow<'a, HashMap<String, PasswordStore>>,
+    },
+    /// Locks the DataStore, and stops the agent.
+    Lock,
+}
+
+/// Responses of the agent.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+enum Response<'a> {
+    /// Password datasets of the DataStore, and their revision.
+    Data {
+        revision: u64,
+        data: Cow<'a, HashMap<String, PasswordStore>>,
+    },
+    /// The DataStore has been saved, with a new revision.
+    Saved { revision: u64 },
+    /// The DataStore has been locked.
+    Locked,
+    /// The DataStore has been changed since the given revision.
+    Conflict,
+    /// The request failed.
+    Error { message: String },
+}
+
+/// Client of an agent holding an unlocked DataStore.
+#[derive(Debug)]
+pub struct AgentClient {
+    /// Path of the agent socket.
+    socket_path: PathBuf,
+    /// Revision of the DataStore last got from the agent.
+    revision: u64,
+}
+
+impl AgentClient {
+    /// Connects to the agent holding a given DataStore, if it is running.
+    pub fn connect(path: &Path) -> Option<AgentClient> {
+        let socket_path = socket_path(path).ok()?;
+
+        #[cfg(unix)]
+        if UnixStream::connect(&socket_path).is_ok() {
+            return Some(AgentClient {
+                socket_path,
+                revision: 0,
+            });
+        }
+
+        None
+    }
+
+    /// returns the path of the agent socket.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Gets the password datasets of the DataStore held by the agent.
+    ///
+    /// This will return an error if the agent cannot be reached.
+    pub fn get(&mut self) -> Result<HashMap<String, PasswordStore>> {
+        match self.send(&Request::Get)? {
+            Response::Data { revision, data } => {
+                self.revision = revision;
+                Ok(data.into_owned())
+            }
+            response => bail!(unexpected(response)),
+        }
+    }
+
+    /// Replaces the password datasets of the DataStore held by the agent, which saves it.
+    ///
+    /// This will return an error if :
+    /// - the agent cannot be reached.
+    /// - the DataStore has been changed by another command since it has been got.
+    /// - the DataStore cannot be saved by the agent.
+    pub fn put(&mut self, data: &HashMap<String, PasswordStore>) -> Result<()> {
+        let request = Request::Put {
+            revision: self.revision,
+            data: Cow::Borrowed(data),
+        };
+
+        match self.send(&request)? {
+            Response::Saved { revision } => {
+                self.revision = revision;
+                Ok(())
+            }
+            response => bail!(unexpected(response)),
+        }
+    }
+
+    /// Locks the DataStore held by the agent, which stops.
+    ///
+    /// This will return an error if the agent cannot be reached.
+    pub fn lock(self) -> Result<()> {
+        match self.send(&Request::Lock)? {
+            Response::Locked => Ok(()),
+            response => bail!(unexpected(response)),
+        }
+    }
+
+    /// Sends a request to the agent, and waits for its response.
+    #[cfg(unix)]
+    fn send(&self, request: &Request) -> Result<Response<'static>> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        stream.set_read_timeout(Some(MESSAGE_TIMEOUT))?;
+        stream.set_write_timeout(Some(MESSAGE_TIMEOUT))?;
+
+        write_message(&mut stream, request)?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+
+        read_message(&mut stream)
+    }
+
+    /// Sends a request to the agent, which is not supported on this platform.
+    #[cfg(not(unix))]
+    fn send(&self, _request: &Request) -> Result<Response<'static>> {
+        bail!(AgentError::Unsupported)
+    }
+}
+
+/// returns the path of the agent socket of a given DataStore file.
+pub fn socket_path(path: &Path) -> Result<PathBuf> {
+    storage::suffixed_path(path, "agent")
+}
+
+/// Starts an agent in the background, holding a given DataStore unlocked.
+///
+/// The agent is the current executable, run in the foreground in a new session.
+/// It is given the master password through its standard input, and is started
+/// once it has unlocked the DataStore.
+///
+/// Returns the PID of the agent.
+///
+/// This will return an error if :
+/// - the agent cannot be run.
+/// - the agent fails to unlock the DataStore.
+#[cfg(unix)]
+pub fn spawn(
+    path: &Path,
+    keyfile: Option<&Path>,
+    master_password: &SecretString,
+    timeout: u64,
+) -> Result<u32> {
+    use std::os::unix::process::CommandExt;
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command.arg("--store").arg(path);
+
+    if let Some(keyfile) = keyfile {
+        command.arg("--keyfile").arg(keyfile);
+    }
+
+    command
+        .arg("--password-stdin")
+        .args(["agent", "--foreground", "--timeout", &timeout.to_string()])
+        .env_remove("RPASS_STORE")
+        .env_remove("RPASS_VAULT")
+        .env_remove("RPASS_KEYFILE")
+        .env_remove("RPASS_PASSWORD_COMMAND")
+        .env_remove(RPASS_MASTER_PASSWORD_ENV)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // SAFETY: setsid is async-signal-safe, the agent is detached from the terminal
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let mut input = Zeroizing::new(master_password.expose().as_bytes().to_vec());
+        input.push(b'\n');
+        stdin.write_all(&input)?;
+    }
+
+    // the agent writes a line on its standard output once it is ready
+    let mut ready = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        BufReader::new(stdout).read_line(&mut ready)?;
+    }
+
+    if ready.is_empty() {
+        let output = child.wait_with_output()?;
+        let error = String::from_utf8_lossy(&output.stderr);
+
+        // the error of the agent is already formatted as an rpass error
+        let error = error.trim();
+        let message = error
+            .split_once("ERROR] ")
+            .map_or(error, |(_, message)| message);
+
+        bail!(AgentError::StartFailed(message.to_string()));
+    }
+
+    Ok(child.id())
+}
+
+/// Starts an agent in the background, which is not supported on this platform.
+#[cfg(not(unix))]
+pub fn spawn(
+    _path: &Path,
+    _keyfile: Option<&Path>,
+    _master_password: &SecretString,
+    _timeout: u64,
+) -> Result<u32> {
+    bail!(AgentError::Unsupported)
+}
+
+/// Serves an unlocked DataStore through the agent socket, until locked or idle for `timeout`.
+///
+/// - the socket is only accessible by the current user.
+/// - `on_ready` is called once the socket is listening.
+/// - the DataStore is closed without being written once the agent stops, as every
+///   change is saved as soon as it is received.
+///
+/// This will return an error if :
+/// - an agent is already running for the DataStore.
+/// - the socket cannot be created.
+/// - a change cannot be saved.
+#[cfg(unix)]
+pub fn serve(
+    opened: DataStore<Unlocked>,
+    timeout: Duration,
+    on_ready: impl FnOnce(&Path),
+) -> Result<()> {
+    let socket = AgentSocket::bind(&socket_path(opened.path())?)?;
+
+    on_ready(&socket.path);
+
+    let mut modified = opened.modify();
+    let mut revision = 0;
+    let mut deadline = Instant::now() + timeout;
+
+    loop {
+        let stream = match socket.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    break;
+                }
+
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(error) => bail!(error),
+        };
+
+        deadline = Instant::now() + timeout;
+
+        if serve_connection(stream, &mut modified, &mut revision)? {
+            break;
+        }
+    }
+
+    modified.close();
+
+    Ok(())
+}
+
+/// Serves a DataStore, which is not supported on this platform.
+#[cfg(not(unix))]
+pub fn serve(
+    _opened: DataStore<Unlocked>,
+    _timeout: Duration,
+    _on_ready: impl FnOnce(&Path),
+) -> Result<()> {
+    bail!(AgentError::Unsupported)
+}
+
+/// Serves a single request of an agent client.
+///
+/// Neither a faulty client nor a failed save stops the agent: the failure is reported
+/// to the client, and the DataStore keeps its last saved content.
+///
+/// Returns whether the DataStore must be locked.
+#[cfg(unix)]
+fn serve_connection(
+    mut stream: UnixStream,
+    modified: &mut DataStore<Dirty>,
+    revision: &mut u64,
+) -> Result<bool> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(MESSAGE_TIMEOUT))?;
+    stream.set_write_timeout(Some(MESSAGE_TIMEOUT))?;
+
+    let request = match read_message::<Request>(&mut stream) {
+        Ok(request) => request,
+        Err(error) => {
+            let response = Response::Error {
+                message: error.to_string(),
+            };
+            let _ = write_message(&mut stream, &response);
+
+            return Ok(false);
+        }
+    };
+
+    let (response, locked) = match request {
+        Request::Get => {
+            let response = Response::Data {
+                revision: *revision,
+                data: Cow::Borrowed(modified.data()),
+            };
+            let _ = write_message(&mut stream, &response);
+
+            return Ok(false);
+        }
+        Request::Put {
+            revision: client_revision,
+            ..
+        } if client_revision != *revision => (Response::Conflict, false),
+        Request::Put { data, .. } => {
+            let previous = modified.data().clone();
+            modified.replace_all(data.into_owned());
+
+            match modified.flush() {
+                Ok(()) => {
+                    *revision += 1;
+
+                    (
+                        Response::Saved {
+                            revision: *revision,
+                        },
+                        false,
+                    )
+                }
+                Err(error) => {
+                    modified.replace_all(previous);
+
+                    (
+                        Response::Error {
+                            message: error.to_string(),
+                        },
+                        false,
+                    )
+                }
+            }
+        }
+        Request::Lock => (Response::Locked, true),
+    };
+
+    let _ = write_message(&mut stream, &response);
+
+    Ok(locked)
+}
+
+/// Listening socket of the agent, removed when dropped.
+#[cfg(unix)]
+struct AgentSocket {
+    /// Path of the socket.
+    path: PathBuf,
+    /// Non-blocking listener of the socket.
+    listener: UnixListener,
+}
+
+#[cfg(unix)]
+impl AgentSocket {
+    /// Binds the agent socket, only accessible by the current user.
+    ///
+    /// A socket left by a stopped agent is removed beforehand.
+    ///
+    /// This will return an error if :
+    /// - another agent is listening on the socket.
+    /// - the socket cannot be created.
+    fn bind(path: &Path) -> Result<AgentSocket> {
+        if UnixStream::connect(path).is_ok() {
+            bail!(AgentError::AlreadyRunning(path.to_path_buf()));
+        }
+
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => bail!(error),
+            _ => {}
+        }
+
+        // the socket is created with 0600 permissions, without any window for other users.
+        // The umask is process-wide, so this is not thread-safe: files created meanwhile
+        // by other threads get the same permissions. The agent binds its socket before
+        // doing anything else, and the umask is restored right after.
+        // SAFETY: `umask` only swaps the file mode creation mask, and cannot fail.
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(path);
+        // SAFETY: same as above, restoring the previous mask.
+        unsafe { libc::umask(umask) };
+
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+
+        Ok(AgentSocket {
+            path: path.to_path_buf(),
+            listener,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for AgentSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Writes a message, as a line of JSON.
+fn write_message(stream: &mut impl Write, message: &impl Serialize) -> Result<()> {
+    let mut content = Zeroizing::new(serde_json::to_vec(message)?);
+    content.push(b'\n');
+
+    stream.write_all(&content)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Reads a message, as a line of JSON.
+fn read_message<T: for<'de> Deserialize<'de>>(stream: &mut impl Read) -> Result<T> {
+    let mut content = Zeroizing::new(Vec::new());
+
+    BufReader::new(stream.take(MAX_MESSAGE_LENGTH)).read_until(b'\n', &mut content)?;
+
+    Ok(serde_json::from_slice(&content)?)
+}
+
+/// returns the error matching an unexpected response of the agent.
+fn unexpected(response: Response) -> AgentError {
+    match response {
+        Response::Conflict => AgentError::Conflict,
+        Response::Error { message } => AgentError::Failed(message),
+        _ => AgentError::UnexpectedResponse,
+    }
+}
+
+// unit tests for this module.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFolder;
+
+    /// test function for the exchanges between the agent and its clients
+    #[test]
+    fn assert_agent_serves_datastore() {
+        let folder = TempFolder::new("agent");
+        let path = folder.join(".datastore");
+
+        let opened = DataStore::new(path.clone())
+            .initialize("master")
+            .unwrap()
+            .save()
+            .unwrap();
+
+        let (ready_sender, ready_receiver) = std::sync::mpsc::channel();
+        let agent = thread::spawn(move || {
+            serve(opened, Duration::from_secs(60), |_| {
+                ready_sender.send(()).unwrap()
+            })
+        });
+        ready_receiver.recv().unwrap();
+
+        let mut client = AgentClient::connect(&path).unwrap();
+        let mut stale_client = AgentClient::connect(&path).unwrap();
+
+        let mut data = client.get().unwrap();
+        stale_client.get().unwrap();
+        assert!(data.is_empty());
+
+        data.insert("test".into(), PasswordStore::new("test", "password".into()));
+        client.put(&data).unwrap();
+
+        let error = stale_client.put(&HashMap::new()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AgentError>(),
+            Some(AgentError::Conflict)
+        ));
+
+        assert_eq!("password", client.get().unwrap()["test"].password.expose());
+
+        // a failed save is reported, and the agent keeps serving the saved content
+        fs::rename(&path, folder.join("moved")).unwrap();
+        fs::create_dir_all(path.join("folder")).unwrap();
+
+        let error = client.put(&HashMap::new()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AgentError>(),
+            Some(AgentError::Failed(_))
+        ));
+
+        fs::remove_dir_all(&path).unwrap();
+        fs::rename(folder.join("moved"), &path).unwrap();
+
+        assert_eq!("password", client.get().unwrap()["test"].password.expose());
+
+        client.lock().unwrap();
+        agent.join().unwrap().unwrap();
+
+        assert!(AgentClient::connect(&path).is_none());
+        assert!(!socket_path(&path).unwrap().exists());
+
+        let opened = DataStore::new(path.clone()).unlock("master").unwrap();
+        assert!(opened.get("test").is_ok());
+        opened.close();
+    }
+}
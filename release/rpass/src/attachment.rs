@@ -0,0 +1,456 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use chacha20poly1305::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        Payload,
+    },
+    XChaCha20Poly1305,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{crypto, secret::SecretKey, storage};
+
+/// Length of the plain chunks encrypted one by one, so that files are never loaded whole.
+const CHUNK_LENGTH: usize = 64 * 1024;
+
+/// Length of the nonce prefix of a blob, the stream using 5 bytes of the nonce
+/// for the chunk counter and the last chunk flag.
+const STREAM_NONCE_LENGTH: usize = crypto::NONCE_LENGTH - 5;
+
+/// Possible errors while attaching files.
+#[derive(Debug, Error)]
+pub enum AttachmentError {
+    #[error("Blob {0} cannot be deciphered, it is either corrupted or tampered with")]
+    Corrupted(String),
+    #[error("Blob {0} is missing from {1}")]
+    Missing(String, PathBuf),
+    #[error("Deciphered content of blob {0} does not match its digest")]
+    DigestMismatch(String),
+    #[error("File {0} already exists, it will not be overwritten")]
+    OutputExists(PathBuf),
+    #[error("Error while ciphering the blob {0}")]
+    Encryption(String),
+}
+
+/// Representation of a file attached to a password data.
+///
+/// The file is encrypted into a blob, in the blobs folder next to the DataStore,
+/// and only its metadata and key are stored in the DataStore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    /// The name of the attached file, unique among the attachments of the password data.
+    pub name: String,
+    /// The random identifier of the blob, which is also its file name.
+    pub blob: String,
+    /// The key the blob is encrypted with.
+    pub key: SecretKey,
+    /// The hexadecimal SHA-256 digest of the attached file.
+    pub digest: String,
+    /// The size of the attached file, in bytes.
+    pub size: u64,
+    /// The date at which the file has been attached.
+    pub attachment_date: DateTime<Utc>,
+}
+
+impl Attachment {
+    /// Encrypts a file into a new blob of a blobs folder, created if missing.
+    ///
+    /// The file is read and encrypted by chunks (see `encrypt()`), and the blob is
+    /// written to a temporary file flushed to disk first, so that it is either complete
+    /// or missing.
+    ///
+    /// This will return an error if :
+    /// - the file cannot be read.
+    /// - the blob cannot be encrypted or written.
+    pub fn create(blobs_folder: &Path, name: &str, file: &Path) -> Result<Self> {
+        fs::create_dir_all(blobs_folder)?;
+
+        let blob = hex(&crypto::random_bytes::<16>());
+        let key = SecretKey::random();
+
+        let path = blobs_folder.join(&blob);
+        let temp_path = storage::suffixed_path(&path, "tmp")?;
+
+        let mut content = File::open(file)?;
+        let mut blob_file = private_file(&temp_path)?;
+
+        // the blob is flushed to disk before being renamed, so that it is never empty once named
+        let result = encrypt(&mut content, &mut blob_file, &key, &blob).and_then(|result| {
+            blob_file.sync_all()?;
+            Ok(result)
+        });
+
+        let (digest, size) = match result {
+            Ok(result) => result,
+            Err(error) => {
+                let _ = fs::remove_file(&temp_path);
+                bail!(error);
+            }
+        };
+
+        fs::rename(&temp_path, &path)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            blob,
+            key,
+            digest,
+            size,
+            attachment_date: Utc::now(),
+        })
+    }
+
+    /// Decrypts the blob of the attachment into a new file.
+    ///
+    /// The file is written to a temporary file flushed to disk first, and only linked
+    /// to the output once the whole blob has been authenticated and matches the digest
+    /// of the attachment, without ever replacing an existing file.
+    ///
+    /// This will return an error if :
+    /// - the output file already exists.
+    /// - the blob is missing, corrupted or tampered with.
+    /// - the output file cannot be written.
+    pub fn extract(&self, blobs_folder: &Path, output: &Path) -> Result<()> {
+        if output.exists() {
+            bail!(AttachmentError::OutputExists(output.to_path_buf()));
+        }
+
+        let path = blobs_folder.join(&self.blob);
+
+        let mut blob = match File::open(&path) {
+            Ok(blob) => blob,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => bail!(
+                AttachmentError::Missing(self.blob.clone(), blobs_folder.to_path_buf())
+            ),
+            Err(error) => bail!(error),
+        };
+
+        let temp_path = storage::suffixed_path(output, "tmp")?;
+        let mut temp_file = private_file(&temp_path)?;
+
+        let result = decrypt(&mut blob, &mut temp_file, &self.key, &self.blob)
+            .and_then(|digest| match digest == self.digest {
+                true => Ok(temp_file.sync_all()?),
+                false => bail!(AttachmentError::DigestMismatch(self.blob.clone())),
+            })
+            // unlike a rename, a link fails if the output has been created in the meantime
+            .and_then(|_| match fs::hard_link(&temp_path, output) {
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    bail!(AttachmentError::OutputExists(output.to_path_buf()))
+                }
+                result => Ok(result?),
+            });
+
+        let _ = fs::remove_file(&temp_path);
+
+        result
+    }
+}
+
+/// returns the blobs folder of a DataStore, next to its file.
+pub fn blobs_folder(data_store_path: &Path) -> Result<PathBuf> {
+    storage::suffixed_path(data_store_path, "blobs")
+}
+
+/// Formats a number of bytes in a human readable way (e.g. `1.5 MiB`).
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{size} B");
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Encrypts a content into a blob, chunk by chunk, with the XChaCha20Poly1305 STREAM construction.
+///
+/// - The blob starts with a random nonce prefix, followed by the ciphered chunks.
+/// - Each chunk is authenticated along its position, and the last one is flagged, so
+///   that chunks cannot be reordered, and the blob cannot be truncated.
+/// - The identifier of the blob is authenticated along each chunk, so that blobs cannot be swapped.
+///
+/// Returns the hexadecimal SHA-256 digest and the size of the content.
+///
+/// This will return an error if the content cannot be read, or the blob cannot be written.
+fn encrypt(
+    content: &mut impl Read,
+    blob: &mut impl Write,
+    key: &SecretKey,
+    blob_id: &str,
+) -> Result<(String, u64)> {
+    let nonce = crypto::random_bytes::<STREAM_NONCE_LENGTH>();
+    let mut encryptor =
+        EncryptorBE32::<XChaCha20Poly1305>::new(key.expose().into(), nonce.as_ref().into());
+
+    blob.write_all(&nonce)?;
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut chunk = Zeroizing::new(vec![0u8; CHUNK_LENGTH]);
+
+    // a partial chunk is the last one, a full one may be followed by an empty last one
+    let last_length = loop {
+        let length = read_chunk(content, &mut chunk)?;
+
+        hasher.update(&chunk[..length]);
+        size += length as u64;
+
+        if length < CHUNK_LENGTH {
+            break length;
+        }
+
+        let payload = Payload {
+            msg: &chunk,
+            aad: blob_id.as_bytes(),
+        };
+
+        let Ok(ciphertext) = encryptor.encrypt_next(payload) else {
+            bail!(AttachmentError::Encryption(blob_id.into()));
+        };
+
+        blob.write_all(&ciphertext)?;
+    };
+
+    let payload = Payload {
+        msg: &chunk[..last_length],
+        aad: blob_id.as_bytes(),
+    };
+
+    let Ok(ciphertext) = encryptor.encrypt_last(payload) else {
+        bail!(AttachmentError::Encryption(blob_id.into()));
+    };
+
+    blob.write_all(&ciphertext)?;
+    blob.flush()?;
+
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+/// Decrypts a blob written by `encrypt()`, chunk by chunk, into a content.
+///
+/// Returns the hexadecimal SHA-256 digest of the content.
+///
+/// This will return an error if :
+/// - the blob cannot be read, or the content cannot be written.
+/// - the blob is not authenticated (e.g. wrong key, or corrupted, truncated or tampered blob).
+fn decrypt(
+    blob: &mut impl Read,
+    content: &mut impl Write,
+    key: &SecretKey,
+    blob_id: &str,
+) -> Result<String> {
+    let mut nonce = [0u8; STREAM_NONCE_LENGTH];
+
+    if read_chunk(blob, &mut nonce)? < STREAM_NONCE_LENGTH {
+        bail!(AttachmentError::Corrupted(blob_id.into()));
+    }
+
+    let mut decryptor =
+        DecryptorBE32::<XChaCha20Poly1305>::new(key.expose().into(), nonce.as_ref().into());
+
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0u8; CHUNK_LENGTH + crypto::TAG_LENGTH];
+
+    // every chunk but the last one is full
+    let last_length = loop {
+        let length = read_chunk(blob, &mut chunk)?;
+
+        if length < chunk.len() {
+            break length;
+        }
+
+        let payload = Payload {
+            msg: &chunk,
+            aad: blob_id.as_bytes(),
+        };
+
+        let Ok(plaintext) = decryptor.decrypt_next(payload).map(Zeroizing::new) else {
+            bail!(AttachmentError::Corrupted(blob_id.into()));
+        };
+
+        hasher.update(plaintext.as_slice());
+        content.write_all(&plaintext)?;
+    };
+
+    let payload = Payload {
+        msg: &chunk[..last_length],
+        aad: blob_id.as_bytes(),
+    };
+
+    let Ok(plaintext) = decryptor.decrypt_last(payload).map(Zeroizing::new) else {
+        bail!(AttachmentError::Corrupted(blob_id.into()));
+    };
+
+    hasher.update(plaintext.as_slice());
+    content.write_all(&plaintext)?;
+    content.flush()?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Reads a reader until a buffer is full or the end is reached.
+///
+/// Returns the number of bytes read, less than the buffer length only at the end.
+fn read_chunk(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut length = 0;
+
+    while length < buffer.len() {
+        match reader.read(&mut buffer[length..]) {
+            Ok(0) => break,
+            Ok(read) => length += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(length)
+}
+
+/// Creates a new file only readable by the current user, failing if it already exists.
+fn private_file(path: &Path) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    Ok(options.open(path)?)
+}
+
+/// Encodes bytes in lowercase hexadecimal.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFolder;
+
+    /// encrypts then decrypts a content, returning the blob and the deciphered content.
+    fn round_trip(content: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let key = SecretKey::random();
+
+        let mut blob = vec![];
+        let (digest, size) = encrypt(&mut &content[..], &mut blob, &key, "blob").unwrap();
+
+        assert_eq!(content.len() as u64, size);
+        assert_eq!(format!("{:x}", Sha256::digest(content)), digest);
+
+        let mut deciphered = vec![];
+        let decrypted_digest = decrypt(&mut &blob[..], &mut deciphered, &key, "blob").unwrap();
+
+        assert_eq!(digest, decrypted_digest);
+
+        (blob, deciphered)
+    }
+
+    /// test function for the encryption of contents by chunks
+    #[test]
+    fn assert_contents_are_encrypted_by_chunks() {
+        for length in [0, 1, CHUNK_LENGTH - 1, CHUNK_LENGTH, 2 * CHUNK_LENGTH + 7] {
+            let content: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+
+            let (blob, deciphered) = round_trip(&content);
+
+            let chunks = length / CHUNK_LENGTH + 1;
+            assert_eq!(
+                STREAM_NONCE_LENGTH + length + chunks * crypto::TAG_LENGTH,
+                blob.len()
+            );
+            assert_eq!(content, deciphered);
+        }
+    }
+
+    /// test function for the detection of corrupted, truncated, swapped or wrongly keyed blobs
+    #[test]
+    fn assert_tampered_blobs_are_rejected() {
+        let content = vec![42u8; CHUNK_LENGTH + 10];
+        let key = SecretKey::random();
+
+        let mut blob = vec![];
+        encrypt(&mut &content[..], &mut blob, &key, "blob").unwrap();
+
+        let is_rejected = |blob: &[u8], key: &SecretKey, blob_id: &str| {
+            decrypt(&mut &blob[..], &mut vec![], key, blob_id).is_err()
+        };
+
+        assert!(!is_rejected(&blob, &key, "blob"));
+        assert!(is_rejected(&blob, &SecretKey::random(), "blob"));
+        assert!(is_rejected(&blob, &key, "other blob"));
+
+        let mut corrupted = blob.clone();
+        corrupted[STREAM_NONCE_LENGTH + 100] ^= 1;
+        assert!(is_rejected(&corrupted, &key, "blob"));
+
+        // truncated at a chunk boundary, the last chunk flag is missing
+        let truncated = &blob[..STREAM_NONCE_LENGTH + CHUNK_LENGTH + crypto::TAG_LENGTH];
+        assert!(is_rejected(truncated, &key, "blob"));
+        assert!(is_rejected(&blob[..10], &key, "blob"));
+    }
+
+    /// test function for the attachment of files into blobs, and their extraction
+    #[test]
+    fn assert_files_are_attached() {
+        let folder = TempFolder::new("attachment");
+
+        let file = folder.join("cert.pem");
+        fs::write(&file, b"-----BEGIN CERTIFICATE-----").unwrap();
+
+        let blobs = blobs_folder(&folder.join(".datastore")).unwrap();
+        let attachment = Attachment::create(&blobs, "cert.pem", &file).unwrap();
+
+        assert_eq!(27, attachment.size);
+        assert!(blobs.join(&attachment.blob).is_file());
+
+        let output = folder.join("extracted.pem");
+        attachment.extract(&blobs, &output).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), fs::read(&output).unwrap());
+        assert!(!storage::suffixed_path(&output, "tmp").unwrap().exists());
+
+        // existing files are never overwritten
+        assert!(attachment.extract(&blobs, &output).is_err());
+
+        let tampered = Attachment {
+            digest: "0".repeat(64),
+            ..attachment.clone()
+        };
+        let tampered_output = folder.join("tampered.pem");
+        assert!(tampered.extract(&blobs, &tampered_output).is_err());
+        assert!(!tampered_output.exists());
+        assert!(!storage::suffixed_path(&tampered_output, "tmp")
+            .unwrap()
+            .exists());
+
+        fs::remove_file(blobs.join(&attachment.blob)).unwrap();
+        assert!(attachment
+            .extract(&blobs, &folder.join("missing.pem"))
+            .is_err());
+
+        assert_eq!("512 B", format_size(512));
+        assert_eq!("1.5 MiB", format_size(1536 * 1024));
+    }
+}
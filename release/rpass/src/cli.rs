@@ -1,13 +1,60 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::constants::{
+    RPASS_AGENT_TIMEOUT_SECS, RPASS_CLIPBOARD_CLEAR_SECS, RPASS_EXPIRY_WARNING_DAYS,
+    RPASS_REVEAL_SECS, RPASS_SHELL_TIMEOUT_SECS,
+};
 
 /// A representation of the CLI command with its own options and args.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// master password to unlock the DataStore
-    #[arg(short, long)]
+    /// master password to unlock the DataStore (⚠️ visible by other processes, prefer other password options)
+    #[arg(short, long, global = true, group = "password_source")]
     pub master_password: Option<String>,
 
+    /// reads the master password from the first line of the standard input
+    #[arg(long, global = true, group = "password_source")]
+    pub password_stdin: bool,
+
+    /// reads the master password from the first line of a file
+    #[arg(long, global = true, group = "password_source")]
+    pub password_file: Option<PathBuf>,
+
+    /// reads the master password from the first line of an inherited file descriptor
+    #[arg(long, global = true, group = "password_source", value_parser = clap::value_parser!(i32).range(0..))]
+    pub password_fd: Option<i32>,
+
+    /// reads the master password from the RPASS_MASTER_PASSWORD environment variable
+    #[arg(long, global = true, group = "password_source")]
+    pub password_env: bool,
+
+    /// shell command printing the master password on its standard output
+    #[arg(long, global = true, env = "RPASS_PASSWORD_COMMAND")]
+    pub password_command: Option<String>,
+
+    /// path of the DataStore file to use (takes precedence over --vault)
+    #[arg(short, long, global = true, env = "RPASS_STORE")]
+    pub store: Option<PathBuf>,
+
+    /// name of the vault to use, each vault being a separate DataStore
+    #[arg(short, long, global = true, env = "RPASS_VAULT")]
+    pub vault: Option<String>,
+
+    /// keyfile required along the master password (generated by rpass init if missing)
+    #[arg(short, long, global = true, env = "RPASS_KEYFILE")]
+    pub keyfile: Option<PathBuf>,
+
+    /// shell command writing its standard input to the clipboard (detected if missing: wl-copy, xclip, xsel or pbcopy)
+    #[arg(long, global = true, env = "RPASS_CLIPBOARD_COMMAND")]
+    pub clipboard_command: Option<String>,
+
+    /// shell command printing the clipboard content, checked before clearing the clipboard
+    #[arg(long, global = true, env = "RPASS_CLIPBOARD_PASTE_COMMAND")]
+    pub clipboard_paste_command: Option<String>,
+
     /// sub-command to actually run a part of the program.
     #[command(subcommand)]
     pub command: Command,
@@ -20,18 +67,262 @@ pub enum Command {
     List,
     /// Initializes a new DataStore
     Init,
-    /// Adds a new password to the DataStore
+    /// Adds a new password (or a note, a card, an identity, a Wi-Fi network) to the DataStore
     Add,
+    /// Edits an existing password of the DataStore
+    Edit {
+        /// name of the password to edit
+        name: String,
+    },
     /// Delete a given password from the DataStore
     Delete {
-        /// name of the password to delete
-        name: String,
+        /// name of the password to delete (picked among the matching ones if not exact)
+        name: Option<String>,
     },
-    /// Dumps a given password into standard output
+    /// Dumps a given password (or one of its custom fields) into standard output
     Dump {
-        /// name of the password to dump
+        /// name of the password to dump (picked among the matching ones if not exact)
+        name: Option<String>,
+        /// name of a custom field to dump in place of the password
+        #[arg(short, long)]
+        field: Option<String>,
+    },
+    /// Attaches a file to a given password, encrypted next to the DataStore
+    Attach {
+        /// name of the password to attach the file to (picked among the matching ones if not exact)
+        name: String,
+        /// path of the file to attach (e.g. a certificate or a key file)
+        file: PathBuf,
+    },
+    /// Extracts a file attached to a given password
+    Detach {
+        /// name of the password the file is attached to (picked among the matching ones if not exact)
         name: String,
+        /// name of the attached file
+        attachment: String,
+        /// path of the extracted file, which must not exist
+        #[arg(short, long, required_unless_present = "remove")]
+        output: Option<PathBuf>,
+        /// removes the attached file from the password, after extracting it if an output is given
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Shows all the fields of a given password, the password being masked
+    Show {
+        /// name of the password to show (picked among the matching ones if not exact)
+        name: Option<String>,
+        /// reveals the password for a while, then clears it from the terminal
+        #[arg(short, long)]
+        reveal: bool,
+        /// time during which the password is revealed, in seconds
+        #[arg(short, long, default_value_t = RPASS_REVEAL_SECS, requires = "reveal", value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: u64,
+    },
+    /// Copies a field of a given password to the clipboard, cleared after a while
+    Copy {
+        /// name of the password to copy (picked among the matching ones if not exact)
+        name: Option<String>,
+        /// field of the password to copy
+        #[arg(short, long, value_enum, default_value_t = CopiedField::Password)]
+        field: CopiedField,
+        /// time after which the clipboard is cleared, if it still holds the copied value, in seconds
+        #[arg(short, long, default_value_t = RPASS_CLIPBOARD_CLEAR_SECS, value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: u64,
+    },
+    /// Clears the clipboard after a while, if it still holds the value whose digest is read from the standard input
+    #[command(hide = true)]
+    ClearClipboard {
+        /// time to wait before clearing the clipboard, in seconds
+        #[arg(long)]
+        after: u64,
+    },
+    /// Prints the current two-factor code of a given password, or imports its OTP secret
+    Otp {
+        /// name of the password (picked among the matching ones if not exact)
+        name: Option<String>,
+        /// imports the OTP secret of the password, from an otpauth:// URI or a base32 string
+        #[arg(long, conflicts_with = "remove")]
+        set: bool,
+        /// removes the OTP secret of the password
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Searches the passwords by fuzzy matching their label, login, URL, comment and fields
+    Search {
+        /// text to search, whose characters must appear in order
+        query: String,
+    },
+    /// Reports the passwords expired or expiring soon, and fails if there are any (e.g. in cron jobs)
+    Expiring {
+        /// period in which passwords are expiring soon, in days (e.g. 30d) or weeks (e.g. 4w)
+        #[arg(short, long, default_value_t = RPASS_EXPIRY_WARNING_DAYS, value_parser = parse_days)]
+        within: i64,
     },
     /// Generates a new strong password and stores it
     Generate,
+    /// Lists the previous passwords of a given password
+    History {
+        /// name of the password to list the history of
+        name: String,
+        /// displays the previous passwords instead of masking them
+        #[arg(short, long)]
+        reveal: bool,
+    },
+    /// Restores a previous password of a given password
+    Restore {
+        /// name of the password to restore
+        name: String,
+        /// version of the password to restore (see rpass history)
+        #[arg(short = 'n', long)]
+        version: usize,
+    },
+    /// Changes the master password of the DataStore
+    ChangeMaster,
+    /// Manages the encrypted backups of the DataStore
+    Backups {
+        /// backup operation to run
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+    /// Starts an agent keeping the DataStore unlocked, until idle for a timeout
+    Agent {
+        /// idle time after which the agent locks the DataStore, in seconds
+        #[arg(short, long, default_value_t = RPASS_AGENT_TIMEOUT_SECS, value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: u64,
+        /// runs the agent in the foreground, instead of detaching it from the terminal
+        #[arg(short, long)]
+        foreground: bool,
+    },
+    /// Locks the DataStore held by the agent, and stops it
+    Lock,
+    /// Browses the DataStore in a full-screen terminal interface
+    Tui,
+    /// Opens an interactive shell on the unlocked DataStore, until idle for a timeout
+    Shell {
+        /// idle time after which the shell saves and locks the DataStore, in seconds
+        #[arg(short, long, default_value_t = RPASS_SHELL_TIMEOUT_SECS, value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: u64,
+    },
+    /// Manages the key derivation of the master password
+    Kdf {
+        /// key derivation operation to run
+        #[command(subcommand)]
+        command: KdfCommand,
+    },
+}
+
+/// Possible fields of a password copied to the clipboard.
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum CopiedField {
+    Login,
+    Password,
+    Url,
+}
+
+/// Possible backup operations.
+#[derive(Subcommand, Clone, PartialEq)]
+pub enum BackupsCommand {
+    /// List the encrypted backups of the DataStore
+    List,
+    /// Restores an encrypted backup over the DataStore
+    Restore {
+        /// version of the backup to restore (see rpass backups list)
+        version: usize,
+    },
+}
+
+/// Possible key derivation operations.
+#[derive(Subcommand, Clone, PartialEq)]
+pub enum KdfCommand {
+    /// Benchmarks the key derivation on this machine, and stores the resulting parameters in the DataStore
+    Calibrate {
+        /// target duration of a key derivation, in milliseconds
+        #[arg(short, long, default_value_t = 500, value_parser = clap::value_parser!(u64).range(1..))]
+        target_ms: u64,
+    },
+}
+
+/// Parses a number of days, given in days (e.g. `30d` or `30`) or in weeks (e.g. `4w`).
+fn parse_days(value: &str) -> Result<i64, String> {
+    let (number, days_per_unit) = match value.strip_suffix('w') {
+        Some(weeks) => (weeks, 7),
+        None => (value.strip_suffix('d').unwrap_or(value), 1),
+    };
+
+    number
+        .parse::<u32>()
+        .map(|number| i64::from(number) * days_per_unit)
+        .map_err(|_| "expected a number of days (e.g. 30d) or weeks (e.g. 4w)".to_string())
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    /// test function for the consistency of the arguments (e.g. unique short options)
+    #[test]
+    fn assert_cli_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    /// test function for the precedence of the store path over the vault name
+    #[test]
+    fn assert_store_is_allowed_along_vault() {
+        let cli = Cli::try_parse_from(["rpass", "-v", "work", "-s", "/tmp/store", "list"]).unwrap();
+
+        assert_eq!(Some(PathBuf::from("/tmp/store")), cli.store);
+        assert_eq!(Some("work".to_string()), cli.vault);
+
+        let cli = Cli::try_parse_from(["rpass", "restore", "github", "-n", "1"]).unwrap();
+        assert!(
+            cli.command
+                == Command::Restore {
+                    name: "github".into(),
+                    version: 1
+                }
+        );
+    }
+
+    /// test function for the master password options, given after the subcommand as well
+    #[test]
+    fn assert_password_options_are_global() {
+        let cli = Cli::try_parse_from(["rpass", "list", "-m", "master"]).unwrap();
+        assert_eq!(Some("master".to_string()), cli.master_password);
+        assert!(
+            Cli::try_parse_from(["rpass", "list", "-m", "master", "--password-stdin"]).is_err()
+        );
+    }
+
+    /// test function for the parsing of periods in days
+    #[test]
+    fn assert_days_are_parsed() {
+        assert_eq!(Ok(30), parse_days("30d"));
+        assert_eq!(Ok(30), parse_days("30"));
+        assert_eq!(Ok(28), parse_days("4w"));
+        assert_eq!(Ok(0), parse_days("0d"));
+
+        assert!(parse_days("-1d").is_err());
+        assert!(parse_days("1m").is_err());
+        assert!(parse_days("d").is_err());
+    }
+
+    /// test function for the output or removal required to detach a file
+    #[test]
+    fn assert_detach_requires_output_or_removal() {
+        assert!(Cli::try_parse_from(["rpass", "detach", "github", "key.pem"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["rpass", "detach", "github", "key.pem", "--remove"]).unwrap();
+        assert!(
+            cli.command
+                == Command::Detach {
+                    name: "github".into(),
+                    attachment: "key.pem".into(),
+                    output: None,
+                    remove: true
+                }
+        );
+    }
 }
@@ -0,0 +1,287 @@
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{constants::RPASS_MASTER_PASSWORD_ENV, utils::shell_command};
+
+/// Clipboard commands looked for when none is configured, in order of preference.
+///
+/// Each backend is described as (environment variable required, program looked for
+/// in the PATH, copy command, paste command).
+const BACKENDS: [(Option<&str>, &str, &str, &str); 4] = [
+    (
+        Some("WAYLAND_DISPLAY"),
+        "wl-copy",
+        "wl-copy",
+        "wl-paste --no-newline",
+    ),
+    (
+        Some("DISPLAY"),
+        "xclip",
+        "xclip -selection clipboard",
+        "xclip -selection clipboard -o",
+    ),
+    (
+        Some("DISPLAY"),
+        "xsel",
+        "xsel --clipboard --input",
+        "xsel --clipboard --output",
+    ),
+    (None, "pbcopy", "pbcopy", "pbpaste"),
+];
+
+/// Possible errors while operating the clipboard.
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("No clipboard command found, set one with --clipboard-command (e.g. \"xclip -selection clipboard\")")]
+    NoBackend,
+    #[error("Clipboard command \"{0}\" failed ({1})")]
+    CommandFailed(String, String),
+}
+
+/// The system clipboard, operated through external commands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clipboard {
+    /// Shell command writing its standard input to the clipboard.
+    copy_command: String,
+    /// Shell command printing the clipboard content, if any.
+    paste_command: Option<String>,
+}
+
+impl Clipboard {
+    /// Creates a clipboard operated by the given commands, or by the commands
+    /// of the first backend available on the system.
+    ///
+    /// This will return an error if no copy command is given and no backend is found.
+    pub fn new(copy_command: Option<&str>, paste_command: Option<&str>) -> Result<Self> {
+        // empty commands (e.g. from empty environment variables) are not configured
+        let copy_command = copy_command.filter(|command| !command.is_empty());
+        let paste_command = paste_command.filter(|command| !command.is_empty());
+
+        if let Some(copy_command) = copy_command {
+            return Ok(Self {
+                copy_command: copy_command.to_string(),
+                paste_command: paste_command.map(str::to_string),
+            });
+        }
+
+        let Some((_, _, copy_command, detected_paste_command)) =
+            BACKENDS.iter().find(|(variable, program, _, _)| {
+                variable.is_none_or(|variable| env::var_os(variable).is_some())
+                    && is_in_path(program)
+            })
+        else {
+            bail!(ClipboardError::NoBackend);
+        };
+
+        Ok(Self {
+            copy_command: copy_command.to_string(),
+            paste_command: Some(paste_command.unwrap_or(detected_paste_command).to_string()),
+        })
+    }
+
+    /// returns either the clipboard content can be read, to check it before clearing it.
+    pub fn can_paste(&self) -> bool {
+        self.paste_command.is_some()
+    }
+
+    /// Writes a value to the clipboard.
+    ///
+    /// The value is given to the copy command through its standard input, and never
+    /// through its arguments (visible by other processes).
+    ///
+    /// This will return an error if the copy command cannot be run or fails.
+    pub fn copy(&self, value: &[u8]) -> Result<()> {
+        // the outputs are not captured, as some commands keep them open while serving the clipboard
+        let mut child = shell_command(&self.copy_command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(value)?;
+        }
+
+        let status = child.wait()?;
+
+        if !status.success() {
+            bail!(ClipboardError::CommandFailed(
+                self.copy_command.clone(),
+                status.to_string()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the clipboard content, if a paste command is known.
+    ///
+    /// This will return an error if the paste command cannot be run or fails.
+    pub fn paste(&self) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let Some(paste_command) = &self.paste_command else {
+            return Ok(None);
+        };
+
+        let output = shell_command(paste_command)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+
+        let content = Zeroizing::new(output.stdout);
+
+        if !output.status.success() {
+            bail!(ClipboardError::CommandFailed(
+                paste_command.clone(),
+                output.status.to_string()
+            ));
+        }
+
+        Ok(Some(content))
+    }
+
+    /// Clears the clipboard, only if it still holds the value of a given digest
+    /// (see `digest()`).
+    ///
+    /// Returns either the clipboard has been cleared, which is never the case when
+    /// its content cannot be read.
+    ///
+    /// This will return an error if the clipboard commands cannot be run or fail.
+    pub fn clear_if_unchanged(&self, value_digest: &str) -> Result<bool> {
+        let Some(content) = self.paste()? else {
+            return Ok(false);
+        };
+
+        // some paste commands append a new line to the content
+        let unchanged = digest(&content) == value_digest
+            || content
+                .strip_suffix(b"\n")
+                .is_some_and(|content| digest(content) == value_digest);
+
+        if unchanged {
+            self.copy(b"")?;
+        }
+
+        Ok(unchanged)
+    }
+
+    /// Starts a process in the background, clearing the clipboard after a given
+    /// time if it still holds a given value.
+    ///
+    /// The process is the current executable, detached from the terminal. It is
+    /// only given the digest of the value, through its standard input.
+    ///
+    /// This will return an error if the process cannot be started.
+    pub fn clear_later(&self, value: &[u8], after_secs: u64) -> Result<()> {
+        let mut command = Command::new(env::current_exe()?);
+        command.arg("--clipboard-command").arg(&self.copy_command);
+
+        if let Some(paste_command) = &self.paste_command {
+            command.arg("--clipboard-paste-command").arg(paste_command);
+        }
+
+        command
+            .args(["clear-clipboard", "--after", &after_secs.to_string()])
+            .env_remove(RPASS_MASTER_PASSWORD_ENV)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            // SAFETY: setsid is async-signal-safe, the process survives the terminal
+            unsafe {
+                command.pre_exec(|| {
+                    libc::setsid();
+                    Ok(())
+                });
+            }
+        }
+
+        let mut child = command.spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "{}", digest(value))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// returns the hexadecimal SHA-256 digest of a value, identifying it without revealing it.
+pub fn digest(value: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(value))
+}
+
+/// Checks either a program is found in one of the folders of the PATH.
+fn is_in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|folder| folder.join(program).is_file()))
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFolder;
+
+    /// creates a fake clipboard, stored in a file of a temporary folder.
+    fn fake_clipboard(folder: &TempFolder) -> Clipboard {
+        let file = folder.join("clipboard");
+
+        Clipboard::new(
+            Some(&format!("cat > '{}'", file.display())),
+            Some(&format!("cat '{}'", file.display())),
+        )
+        .unwrap()
+    }
+
+    /// test function for the copy of values to the clipboard
+    #[cfg(unix)]
+    #[test]
+    fn assert_values_are_copied() {
+        let folder = TempFolder::new("clipboard-copy");
+        let clipboard = fake_clipboard(&folder);
+
+        clipboard.copy(b"secret").unwrap();
+        assert_eq!(b"secret", clipboard.paste().unwrap().unwrap().as_slice());
+
+        let failing = Clipboard::new(Some("exit 1"), None).unwrap();
+        assert!(failing.copy(b"secret").is_err());
+        assert!(failing.paste().unwrap().is_none());
+    }
+
+    /// test function for the clearing of the clipboard, only when it still holds the copied value
+    #[cfg(unix)]
+    #[test]
+    fn assert_clipboard_is_cleared_if_unchanged() {
+        let folder = TempFolder::new("clipboard-clear");
+        let clipboard = fake_clipboard(&folder);
+
+        clipboard.copy(b"secret").unwrap();
+        clipboard.copy(b"copied by someone else").unwrap();
+        assert!(!clipboard.clear_if_unchanged(&digest(b"secret")).unwrap());
+        assert_eq!(
+            b"copied by someone else",
+            clipboard.paste().unwrap().unwrap().as_slice()
+        );
+
+        clipboard.copy(b"secret\n").unwrap();
+        assert!(clipboard.clear_if_unchanged(&digest(b"secret")).unwrap());
+        assert!(clipboard.paste().unwrap().unwrap().is_empty());
+
+        // a clipboard which cannot be read is never cleared
+        let blind = Clipboard::new(Some("true"), Some("")).unwrap();
+        assert!(!blind.can_paste());
+        assert!(!blind.clear_if_unchanged(&digest(b"secret")).unwrap());
+    }
+}
@@ -1,11 +1,19 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    io,
+    time::{Duration, Instant},
+};
 
 use comfy_table::{
     modifiers::UTF8_ROUND_CORNERS,
     presets::{NOTHING, UTF8_FULL},
     Attribute, Cell, CellAlignment, Row, Table,
 };
-use console::{style, Emoji, Term};
+use console::{measure_text_width, style, Emoji, Term};
+use crossterm::{
+    event::{self, Event},
+    terminal,
+};
 use indicatif::ProgressBar;
 use inquire::{Confirm, DateSelect, MultiSelect, Password, PasswordDisplayMode, Select, Text};
 
@@ -136,7 +144,7 @@ impl ConsoleIO {
     pub fn warning(&self, text: &str) {
         let warning_symb_str = format!("[{}  WARNING]", Emoji("⚠️", "!"));
 
-        self.stdout
+        self.stderr
             .write_line(&format!(
                 "{} {}",
                 style(warning_symb_str).yellow().bold(),
@@ -277,7 +285,7 @@ impl ConsoleIO {
     /// **Note:** You must use the `prompt()` method to actually display it to the user.
     ///
     /// See https://github.com/mikaelmello/inquire
-    pub fn input_text<'a>(&'a self, question: &'a str) -> Text {
+    pub fn input_text<'a>(&'a self, question: &'a str) -> Text<'a> {
         Text::new(question)
     }
 
@@ -286,7 +294,7 @@ impl ConsoleIO {
     /// **Note:** You must use the `prompt()` method to actually display it to the user.
     ///
     /// See https://github.com/mikaelmello/inquire
-    pub fn input_date<'a>(&'a self, question: &'a str) -> DateSelect {
+    pub fn input_date<'a>(&'a self, question: &'a str) -> DateSelect<'a> {
         DateSelect::new(question)
     }
 
@@ -295,7 +303,11 @@ impl ConsoleIO {
     /// **Note:** You must use the `prompt()` method to actually display it to the user.
     ///
     /// See https://github.com/mikaelmello/inquire
-    pub fn input_select<'a>(&'a self, question: &'a str, choices: Vec<&'a str>) -> Select<&'a str> {
+    pub fn input_select<'a>(
+        &'a self,
+        question: &'a str,
+        choices: Vec<&'a str>,
+    ) -> Select<'a, &'a str> {
         Select::new(question, choices)
     }
 
@@ -308,7 +320,7 @@ impl ConsoleIO {
         &'a self,
         question: &'a str,
         choices: Vec<&'a str>,
-    ) -> MultiSelect<&'a str> {
+    ) -> MultiSelect<'a, &'a str> {
         MultiSelect::new(question, choices)
     }
 
@@ -317,7 +329,7 @@ impl ConsoleIO {
     /// **Note:** You must use the `prompt()` method to actually display it to the user.
     ///
     /// See https://github.com/mikaelmello/inquire
-    pub fn input_confirm<'a>(&'a self, question: &'a str) -> Confirm {
+    pub fn input_confirm<'a>(&'a self, question: &'a str) -> Confirm<'a> {
         Confirm::new(question)
     }
 
@@ -326,7 +338,7 @@ impl ConsoleIO {
     /// **Note:** You must use the `prompt()` method to actually display it to the user.
     ///
     /// See https://github.com/mikaelmello/inquire
-    pub fn input_password<'a>(&'a self, question: &'a str) -> Password {
+    pub fn input_password<'a>(&'a self, question: &'a str) -> Password<'a> {
         Password::new(question)
     }
 
@@ -388,6 +400,32 @@ impl ConsoleIO {
         response.unwrap()
     }
 
+    /// shorthand method to directly ask a multi-line text to the user, typed line by line.
+    ///
+    /// The text ends with the first empty line, and is never written to a file.
+    ///
+    /// This will return an error if STDIN cannot be read.
+    pub fn ask_multiline(&self, question: &str) -> io::Result<String> {
+        self.writeln(&format!(
+            "{} {} {}",
+            style("?").green(),
+            question,
+            style("(finish with an empty line)").dim()
+        ));
+
+        let mut lines = vec![];
+
+        loop {
+            let line = self.stdout.read_line()?;
+
+            if line.is_empty() {
+                return Ok(lines.join("\n"));
+            }
+
+            lines.push(line);
+        }
+    }
+
     /// creates a progress bar for the user.
     ///
     /// - use `inc(u64)` to increment the bar.
@@ -406,4 +444,64 @@ impl ConsoleIO {
     pub fn create_spinner(&self) -> ProgressBar {
         ProgressBar::new_spinner()
     }
+
+    /// displays a secret for a given duration (or until a key is pressed), then clears it
+    /// from STDOUT, so that it does not stay in the terminal scrollback.
+    ///
+    /// **Note:** STDOUT must be a terminal.
+    pub fn reveal(&self, label: &str, secret: &str, duration: Duration) -> io::Result<()> {
+        let text = format!("{} {secret}", style(format!("{label}:")).white().bold());
+        self.stdout.write_line(&text)?;
+
+        // a multi-line secret spans one line per line break, and each of its
+        // lines is wrapped on several ones when longer than the terminal
+        let (_, columns) = self.stdout.size();
+        let lines = text
+            .split('\n')
+            .map(|line| {
+                measure_text_width(line)
+                    .max(1)
+                    .div_ceil(columns.max(1) as usize)
+            })
+            .sum();
+
+        terminal::enable_raw_mode()?;
+        let countdown = self.reveal_countdown(duration);
+        terminal::disable_raw_mode()?;
+
+        self.stdout.clear_line()?;
+        self.stdout.clear_last_lines(lines)?;
+
+        countdown
+    }
+
+    /// displays the countdown of a revealed secret, until it ends or a key is pressed.
+    fn reveal_countdown(&self, duration: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + duration;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                return Ok(());
+            }
+
+            let seconds = remaining.as_millis().div_ceil(1000);
+
+            self.stdout.clear_line()?;
+            self.stdout.write_str(&format!(
+                "{}",
+                style(format!(
+                    "Hidden in {seconds}s, press any key to hide it now"
+                ))
+                .dim()
+            ))?;
+
+            if event::poll(remaining.min(Duration::from_secs(1)))? {
+                if let Event::Key(_) = event::read()? {
+                    return Ok(());
+                }
+            }
+        }
+    }
 }
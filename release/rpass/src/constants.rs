@@ -3,3 +3,33 @@ pub const RPASS_SUBFOLDER: &str = ".rpass";
 
 /// Name of the actual DataStore file.
 pub const RPASS_DATASTORE_FILENAME: &str = ".datastore";
+
+/// Extension of the named vault files, stored in the home subfolder.
+pub const RPASS_VAULT_EXTENSION: &str = "datastore";
+
+/// Maximum number of previous passwords kept in each password history.
+pub const RPASS_PASSWORD_HISTORY_SIZE: usize = 10;
+
+/// Number of encrypted backups kept for each DataStore file.
+pub const RPASS_BACKUP_COUNT: usize = 5;
+
+/// Maximum time to wait for another rpass process to release a DataStore, in seconds.
+pub const RPASS_LOCK_TIMEOUT_SECS: u64 = 10;
+
+/// Environment variable holding the master password, only read with `--password-env`.
+pub const RPASS_MASTER_PASSWORD_ENV: &str = "RPASS_MASTER_PASSWORD";
+
+/// Default idle time after which the agent locks the DataStore, in seconds.
+pub const RPASS_AGENT_TIMEOUT_SECS: u64 = 15 * 60;
+
+/// Default idle time after which the interactive shell locks the DataStore, in seconds.
+pub const RPASS_SHELL_TIMEOUT_SECS: u64 = 5 * 60;
+
+/// Default time during which `rpass show --reveal` displays a password, in seconds.
+pub const RPASS_REVEAL_SECS: u64 = 10;
+
+/// Default time after which `rpass copy` clears the clipboard, in seconds.
+pub const RPASS_CLIPBOARD_CLEAR_SECS: u64 = 30;
+
+/// Number of days before its expiration date from which a password is flagged as expiring soon.
+pub const RPASS_EXPIRY_WARNING_DAYS: i64 = 30;
@@ -1,91 +1,268 @@
 use anyhow::{bail, Result};
-use chacha20poly1305::aead::Aead;
+use chacha20poly1305::aead::{Aead, Payload};
 use chacha20poly1305::XChaCha20Poly1305;
 use chacha20poly1305::{aead::OsRng, KeyInit};
 use rand::RngCore;
-use serde::{Deserialize, Serialize};
-
-/// Representation of a crypted message.
-#[derive(Default, Debug, Serialize, Deserialize)]
-pub struct EncryptedMessage {
-    /// Cryptographic nonce.
-    nonce: [u8; 24],
-    /// Password salt.
-    salt: [u8; 32],
-    /// Crypted message.
-    message: Vec<u8>,
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use zeroize::Zeroizing;
+
+use crate::secret::SecretKey;
+
+/// Size of the Argon2id salt, in bytes.
+pub const SALT_LENGTH: usize = 32;
+
+/// Size of the XChaCha20Poly1305 nonce, in bytes.
+pub const NONCE_LENGTH: usize = 24;
+
+/// Size of the XChaCha20Poly1305 key, in bytes.
+pub const KEY_LENGTH: usize = 32;
+
+/// Size of the XChaCha20Poly1305 authentication tag, in bytes.
+pub const TAG_LENGTH: usize = 16;
+
+/// Parameters of the Argon2id key derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost, in KiB.
+    pub mem_cost: u32,
+    /// Time cost (e.g. number of iterations).
+    pub time_cost: u32,
+    /// Degree of parallelism.
+    pub lanes: u32,
+}
+
+/// Strong default parameters for the Argon2id key derivation.
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            mem_cost: 16 * 1024,
+            time_cost: 8,
+            lanes: 8,
+        }
+    }
+}
+
+/// Human readable representation of the key derivation parameters.
+impl fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} KiB of memory, {} iterations, {} lanes",
+            self.mem_cost, self.time_cost, self.lanes
+        )
+    }
+}
+
+impl KdfParams {
+    /// Minimum parameters accepted for the Argon2id key derivation.
+    ///
+    /// A key derived with weaker parameters is derived again with stronger ones.
+    pub const MINIMUM: KdfParams = KdfParams {
+        mem_cost: 16 * 1024,
+        time_cost: 3,
+        lanes: 1,
+    };
+
+    /// Maximum parameters accepted for the Argon2id key derivation.
+    ///
+    /// A vault file asking for stronger ones is rejected, so that a crafted file
+    /// cannot exhaust the memory or the time of the machine opening it.
+    pub const MAXIMUM: KdfParams = KdfParams {
+        mem_cost: 1024 * 1024,
+        time_cost: 256,
+        lanes: 16,
+    };
+
+    /// Checks either these parameters are weaker than other ones, in memory or in time.
+    pub fn is_weaker_than(&self, other: &KdfParams) -> bool {
+        self.mem_cost < other.mem_cost || self.time_cost < other.time_cost
+    }
+
+    /// Checks either any of these parameters exceeds the one of other parameters.
+    pub fn exceeds(&self, other: &KdfParams) -> bool {
+        self.mem_cost > other.mem_cost
+            || self.time_cost > other.time_cost
+            || self.lanes > other.lanes
+    }
+
+    /// Returns these parameters, raised to the memory & time costs of other ones if needed.
+    pub fn strengthened(&self, other: &KdfParams) -> KdfParams {
+        KdfParams {
+            mem_cost: self.mem_cost.max(other.mem_cost),
+            time_cost: self.time_cost.max(other.time_cost),
+            lanes: self.lanes,
+        }
+    }
 }
 
-/// Encrpyts a given message with a password security on top.
+/// Maximum memory cost reached by the Argon2id calibration, in KiB.
+const CALIBRATION_MAX_MEM_COST: u32 = 256 * 1024;
+
+/// Derives a 256 bits key from a password, using Argon2id hashing.
 ///
-/// - The password is hashed using Argon2id encryption.
-/// - The message is hashed using XChaCha20Poly1305 symetric encription with the hashed password.
+/// The secret (e.g. a keyfile digest, or empty) is mixed into the Argon2id input.
 ///
-/// This will return an error upon encryption error.
-pub fn encrypt(content: &str, password: &str) -> Result<EncryptedMessage> {
-    let mut message = EncryptedMessage::default();
+/// This will return an error if the Argon2id parameters are invalid.
+pub fn derive_key(
+    password: &str,
+    salt: &[u8],
+    params: &KdfParams,
+    secret: &[u8],
+) -> Result<SecretKey> {
+    let argon2_config = argon2_config(params, secret);
 
-    let argon2_config = argon2_config();
+    let hash = Zeroizing::new(argon2::hash_raw(password.as_bytes(), salt, &argon2_config)?);
 
-    let mut salt = [0u8; 32];
-    let mut nonce = [0u8; 24];
-    OsRng.fill_bytes(&mut salt);
-    OsRng.fill_bytes(&mut nonce);
+    match SecretKey::from_slice(&hash) {
+        Some(key) => Ok(key),
+        None => bail!("Error while deriving key : unexpected key length"),
+    }
+}
 
-    message.salt = salt;
-    message.nonce = nonce;
+/// Measures the duration of an Argon2id key derivation with given parameters.
+///
+/// This will return an error if the Argon2id parameters are invalid.
+pub fn benchmark(params: &KdfParams) -> Result<Duration> {
+    let salt = random_bytes::<SALT_LENGTH>();
+    let start = Instant::now();
 
-    let key = argon2::hash_raw(password.as_bytes(), &salt, &argon2_config)?;
+    derive_key("rpass-calibration", &salt, params, &[])?;
 
-    let cipher = XChaCha20Poly1305::new(key[..32].as_ref().into());
+    Ok(start.elapsed())
+}
 
-    let ciphertext_result = cipher.encrypt(nonce.as_ref().into(), content.as_bytes().as_ref());
+/// Finds the Argon2id parameters for a key derivation to last about a target duration.
+///
+/// Starting from the minimum parameters, the memory cost is doubled first (up to
+/// 256 MiB) as it is the costliest for an attacker, then the time cost is raised.
+/// The minimum parameters are returned if they already exceed the target duration,
+/// and the time cost never exceeds the one of `KdfParams::MAXIMUM`.
+///
+/// This will return an error if a key derivation fails.
+pub fn calibrate(target: Duration) -> Result<KdfParams> {
+    let mut params = KdfParams {
+        lanes: KdfParams::default().lanes,
+        ..KdfParams::MINIMUM
+    };
 
-    if let Err(error) = ciphertext_result {
-        bail!("Error while ciphering data : {error}");
+    let mut elapsed = benchmark(&params)?;
+
+    while elapsed * 2 <= target && params.mem_cost < CALIBRATION_MAX_MEM_COST {
+        params.mem_cost *= 2;
+        elapsed = benchmark(&params)?;
     }
 
-    let ciphertext = ciphertext_result.unwrap();
-    message.message = ciphertext;
+    let pass_duration = elapsed.as_secs_f64() / params.time_cost as f64;
+    let time_cost = (target.as_secs_f64() / pass_duration) as u32;
+
+    params.time_cost = time_cost.clamp(KdfParams::MINIMUM.time_cost, KdfParams::MAXIMUM.time_cost);
 
-    Ok(message)
+    Ok(params)
 }
 
-/// Decrypts a given message with a password.
+/// Encrypts a given content with a key.
 ///
-/// - The password is compared using Argon2id hashing.
-/// - The message is deciphered using XChaCha20Poly1305 symetric encription with the hashed password.
+/// - The content is ciphered using XChaCha20Poly1305 symetric encryption.
+/// - The associated data is not ciphered, but authenticated along the content.
 ///
-/// This will return an error upon decryption error.
-pub fn decrypt(crypted: EncryptedMessage, password: &str) -> Result<String> {
-    let argon2_config = argon2_config();
+/// This will return an error upon encryption error.
+pub fn encrypt(
+    content: &[u8],
+    key: &SecretKey,
+    nonce: &[u8; NONCE_LENGTH],
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(key.expose().into());
 
-    let salt = crypted.salt;
-    let nonce = crypted.nonce;
-    let ciphertext = crypted.message;
+    let payload = Payload {
+        msg: content,
+        aad: associated_data,
+    };
 
-    let key = argon2::hash_raw(password.as_bytes(), &salt, &argon2_config)?;
-    let cipher = XChaCha20Poly1305::new(key[..32].as_ref().into());
+    let ciphertext_result = cipher.encrypt(nonce.as_ref().into(), payload);
 
-    let decoded_result = cipher.decrypt(nonce.as_ref().into(), ciphertext.as_ref());
+    if let Err(error) = ciphertext_result {
+        bail!("Error while ciphering data : {error}");
+    }
+
+    Ok(ciphertext_result.unwrap())
+}
+
+/// Decrypts a given ciphertext with a key.
+///
+/// - The ciphertext is deciphered using XChaCha20Poly1305 symetric encryption.
+/// - The associated data must be the one given upon encryption.
+/// - The deciphered content is wiped from memory upon drop.
+///
+/// This will return an error upon decryption error (e.g. wrong key or tampered data).
+pub fn decrypt(
+    ciphertext: &[u8],
+    key: &SecretKey,
+    nonce: &[u8; NONCE_LENGTH],
+    associated_data: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let cipher = XChaCha20Poly1305::new(key.expose().into());
+
+    let payload = Payload {
+        msg: ciphertext,
+        aad: associated_data,
+    };
+
+    let decoded_result = cipher.decrypt(nonce.as_ref().into(), payload);
 
     if let Err(error) = decoded_result {
         bail!("Error while deciphering data : {error}");
     }
 
-    let decoded = decoded_result.unwrap();
+    Ok(Zeroizing::new(decoded_result.unwrap()))
+}
 
-    Ok(String::from_utf8(decoded).unwrap())
+/// Generates cryptographically secure random bytes (e.g. for salts & nonces).
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+
+    bytes
 }
 
-/// Generates a strong Argon2id configuration for ciphers.
-fn argon2_config<'a>() -> argon2::Config<'a> {
+/// Generates an Argon2id configuration for ciphers, given its parameters and secret.
+fn argon2_config<'a>(params: &KdfParams, secret: &'a [u8]) -> argon2::Config<'a> {
     argon2::Config {
         variant: argon2::Variant::Argon2id,
         hash_length: 32,
-        lanes: 8,
-        mem_cost: 16 * 1024,
-        time_cost: 8,
+        lanes: params.lanes,
+        mem_cost: params.mem_cost,
+        time_cost: params.time_cost,
+        secret,
         ..Default::default()
     }
 }
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the comparison of key derivation parameters
+    #[test]
+    fn assert_weak_kdf_params_are_strengthened() {
+        let weak = KdfParams {
+            mem_cost: 8 * 1024,
+            time_cost: 10,
+            lanes: 4,
+        };
+
+        assert!(weak.is_weaker_than(&KdfParams::MINIMUM));
+        assert!(!KdfParams::default().is_weaker_than(&KdfParams::MINIMUM));
+
+        let strengthened = weak.strengthened(&KdfParams::MINIMUM);
+
+        assert_eq!(KdfParams::MINIMUM.mem_cost, strengthened.mem_cost);
+        assert_eq!(10, strengthened.time_cost);
+        assert_eq!(4, strengthened.lanes);
+        assert!(!strengthened.is_weaker_than(&KdfParams::MINIMUM));
+    }
+}
@@ -1,15 +1,35 @@
 use anyhow::{bail, Result};
-use base64::{engine::general_purpose, Engine};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, marker::PhantomData};
+use std::{
+    collections::HashMap,
+    fs,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
+use zeroize::Zeroizing;
 
 use crate::{
-    crypto::{self, EncryptedMessage},
-    utils::{get_store_file_path, get_store_folder_path},
+    agent::AgentClient,
+    attachment::{self, Attachment},
+    constants::{RPASS_LOCK_TIMEOUT_SECS, RPASS_PASSWORD_HISTORY_SIZE},
+    crypto::KdfParams,
+    entry::EntryKind,
+    fuzzy,
+    keyfile::Keyfile,
+    otp::OtpSecret,
+    secret::SecretString,
+    storage::{self, Backup, LockFile, StorageError},
+    vault_format::{self, KeySlot, VaultKey, VAULT_FORMAT_VERSION},
 };
 
+/// Bonus of a search query matching the label of a password dataset, rather than another field.
+const LABEL_MATCH_BONUS: i64 = 32;
+
 /// Possible errors while operating with the DataStore.
 #[derive(Debug, Error)]
 pub enum DataStoreError {
@@ -19,6 +39,19 @@ pub enum DataStoreError {
     KeyNotFound(String),
     #[error("Key {0} already exists in the datastore")]
     KeyAlreadyExists(String),
+    #[error("Version {1} not found in the history of key {0}")]
+    VersionNotFound(String, usize),
+    #[error("New master password cannot be verified, the datastore has been left untouched")]
+    MasterPasswordVerificationFailed,
+    #[error("Datastore cannot be verified after ciphering, it has been left untouched")]
+    SaveVerificationFailed,
+    #[error("Datastore is in use by another rpass process{}, please retry once it is done (lock file: {})", .pid.map(|pid| format!(" (PID {pid})")).unwrap_or_default(), .lock_path.display())]
+    Locked {
+        pid: Option<u32>,
+        lock_path: PathBuf,
+    },
+    #[error("Datastore is held by the rpass agent, please run `rpass lock` beforehand")]
+    HeldByAgent,
 }
 
 /// DataStore representation for JSON serialization & deserialization.
@@ -38,13 +71,150 @@ pub struct PasswordStore {
     /// A login linked to a password (optionnal).
     pub login: Option<String>,
     /// The actual password.
-    pub password: String,
+    pub password: SecretString,
     /// An URL linked to a password (optionnal).
     pub url: Option<String>,
     /// A comment linked to a password (optionnal).
     pub comment: Option<String>,
     /// The creation date for the password data.
     pub creation_date: DateTime<Utc>,
+    /// The last modification date for the password data (optionnal).
+    #[serde(default)]
+    pub modification_date: Option<DateTime<Utc>>,
+    /// Previous passwords, from the most recent to the oldest one.
+    #[serde(default)]
+    pub history: Vec<PasswordHistory>,
+    /// The two-factor secret generating one-time passwords (optionnal).
+    #[serde(default)]
+    pub otp: Option<OtpSecret>,
+    /// The date from which the password should be changed (optionnal).
+    #[serde(default)]
+    pub expiration_date: Option<NaiveDate>,
+    /// The kind of entry and its specific fields, entries without one being logins.
+    #[serde(default)]
+    pub kind: EntryKind,
+    /// Additional fields (e.g. an API key or a PIN), in the order they were entered.
+    #[serde(default)]
+    pub fields: Vec<CustomField>,
+    /// Files attached to the password (e.g. certificates), encrypted into blobs.
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// Representation of a custom field of a password data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomField {
+    /// The name of the field, unique among the fields of the password data.
+    pub name: String,
+    /// The value of the field.
+    pub value: SecretString,
+    /// Whether the value is secret, and must be masked when shown.
+    pub secret: bool,
+}
+
+/// Representation of a previous password of a password data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordHistory {
+    /// The previous password.
+    pub password: SecretString,
+    /// The date at which this password has been replaced.
+    pub replacement_date: DateTime<Utc>,
+}
+
+impl PasswordStore {
+    /// Creates a login password dataset, created now, without any optionnal field.
+    pub fn new(label: &str, password: SecretString) -> Self {
+        Self {
+            label: label.to_string(),
+            login: None,
+            password,
+            url: None,
+            comment: None,
+            creation_date: Utc::now(),
+            modification_date: None,
+            history: vec![],
+            otp: None,
+            expiration_date: None,
+            kind: EntryKind::Login,
+            fields: vec![],
+            attachments: vec![],
+        }
+    }
+
+    /// Scores how well this password dataset matches a search query, the higher the better.
+    ///
+    /// The query is fuzzy matched against the label, login, URL, comment, searchable
+    /// fields of the kind of entry, and the names and plain values of the custom fields.
+    /// The best matching field is kept, the label being favored.
+    ///
+    /// Returns `None` if no field matches the query.
+    pub fn search_score(&self, query: &str) -> Option<i64> {
+        let label_score = fuzzy::score(query, &self.label).map(|score| score + LABEL_MATCH_BONUS);
+
+        [&self.login, &self.url, &self.comment]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(self.kind.searchable_fields())
+            .chain(self.fields.iter().flat_map(|field| {
+                let value = (!field.secret).then(|| field.value.expose());
+                [Some(field.name.as_str()), value].into_iter().flatten()
+            }))
+            .filter_map(|value| fuzzy::score(query, value))
+            .chain(label_score)
+            .max()
+    }
+
+    /// returns the custom field of a given name, if any.
+    pub fn field(&self, name: &str) -> Option<&CustomField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// returns the number of days left before the password expires (zero or less
+    /// once expired), if it has an expiration date.
+    pub fn days_before_expiration(&self, today: NaiveDate) -> Option<i64> {
+        self.expiration_date
+            .map(|date| date.signed_duration_since(today).num_days())
+    }
+
+    /// Replaces the password, keeping the previous one in the history.
+    ///
+    /// Nothing is recorded if the password does not change, and the history is
+    /// bounded to `RPASS_PASSWORD_HISTORY_SIZE` entries, dropping the oldest ones.
+    pub fn change_password(&mut self, password: &str) {
+        if self.password.expose() == password {
+            return;
+        }
+
+        let previous = std::mem::replace(&mut self.password, password.into());
+
+        self.history.insert(
+            0,
+            PasswordHistory {
+                password: previous,
+                replacement_date: Utc::now(),
+            },
+        );
+        self.history.truncate(RPASS_PASSWORD_HISTORY_SIZE);
+    }
+
+    /// Brings back a previous password given its version.
+    ///
+    /// Versions start at 1 for the most recent previous password. The current
+    /// password is kept in the history, so a restoration can also be undone.
+    ///
+    /// This will return an error if the version is not found in the history.
+    pub fn restore_password(&mut self, version: usize) -> Result<()> {
+        if version == 0 || version > self.history.len() {
+            bail!(DataStoreError::VersionNotFound(self.label.clone(), version));
+        }
+
+        let restored = self.history.remove(version - 1);
+
+        self.change_password(restored.password.expose());
+
+        Ok(())
+    }
 }
 
 /// Locked state representation for the DataStore.
@@ -55,84 +225,226 @@ pub struct Locked;
 #[derive(Default)]
 pub struct Unlocked;
 
+/// Dirty state representation for the DataStore, e.g. unlocked with unsaved changes.
+#[derive(Default)]
+pub struct Dirty;
+
+/// States in which the content of the DataStore can be read.
+pub trait Opened {}
+
+impl Opened for Unlocked {}
+
+impl Opened for Dirty {}
+
 /// Representation of the DataStore.
-#[derive(Default, Debug, Clone)]
+///
+/// The master password is only used to unwrap the DataStore key upon unlock, so a
+/// single key derivation happens per process: the unlocked DataStore keeps the key
+/// to save its content, and its key slots (and their salts) are kept as they are
+/// until the master password changes.
+///
+/// An unlocked DataStore is only written back to the filesystem once it has been
+/// modified (e.g. in the `Dirty` state), otherwise it is closed without any write.
+#[derive(Default, Debug)]
 pub struct DataStore<State = Locked> {
     /// A Map storing all password data.
     data: HashMap<String, PasswordStore>,
-    /// The state of the DataStore, Either Locked or Unlocked.
+    /// The state of the DataStore, Either Locked, Unlocked or Dirty.
     state: PhantomData<State>,
-    /// Master password for this DataStore.
-    master_password: String,
+    /// Random key ciphering the DataStore content.
+    key: VaultKey,
+    /// Key slots holding the key, wrapped by the master password.
+    key_slots: Vec<KeySlot>,
+    /// Path of the DataStore file in the filesystem.
+    path: PathBuf,
+    /// Inter-process lock held while the DataStore is unlocked.
+    lock: Option<LockFile>,
+    /// Keyfile required along the master password (optionnal).
+    keyfile: Option<Keyfile>,
+    /// Whether the DataStore must be saved again to be upgraded.
+    outdated: bool,
+    /// Agent holding the DataStore, when unlocked through it (optionnal).
+    agent: Option<AgentClient>,
+    /// Blobs of the attachments removed since the last save, deleted once it is saved.
+    removed_blobs: Vec<String>,
+    /// Blobs of the attachments added since the last save, deleted if it is never saved.
+    added_blobs: Vec<String>,
 }
 
 /// Implementation for the `Locked` state of the DataStore.
 impl DataStore<Locked> {
+    /// Sets the keyfile to use along the master password to unlock or initialize the DataStore.
+    pub fn with_keyfile(self, keyfile: Option<Keyfile>) -> Self {
+        Self { keyfile, ..self }
+    }
+
     /// Unlocks the DataStore given a master password.
     ///
+    /// If the master password key slot has been derived with parameters weaker than
+    /// `KdfParams::MINIMUM`, the DataStore key is wrapped again with stronger ones,
+    /// and the DataStore is outdated until saved.
+    ///
     /// This will return an error if :
     /// - the DataStore is not initialized.
     /// - the content of the DataStore cannot be loaded (various reasons).
+    /// - the DataStore key cannot be wrapped again.
     pub fn unlock(self, master_password: &str) -> Result<DataStore<Unlocked>> {
         if !self.is_initialized()? {
             bail!(DataStoreError::NotFound);
         }
 
+        let lock = self.acquire_lock()?;
+
         let datastore_content = self.load_content()?;
 
-        let mut buffer = Vec::<u8>::new();
+        let opened =
+            vault_format::open(&datastore_content, master_password, self.keyfile.as_ref())?;
 
-        general_purpose::STANDARD.decode_vec(&datastore_content, &mut buffer)?;
+        let model = serde_json::from_slice::<StoreModel>(&opened.content)?;
 
-        let mid_cipher_content = String::from_utf8(buffer)?;
+        let mut unlocked = DataStore {
+            data: model.data,
+            state: PhantomData::<Unlocked>,
+            key: opened.key,
+            key_slots: opened.slots,
+            path: self.path,
+            lock: Some(lock),
+            keyfile: self.keyfile,
+            outdated: opened.version < VAULT_FORMAT_VERSION,
+            agent: None,
+            removed_blobs: vec![],
+            added_blobs: vec![],
+        };
 
-        let cipher_content = serde_json::from_str::<EncryptedMessage>(&mid_cipher_content)?;
+        let kdf_params = unlocked.kdf_params();
 
-        let store_model_content = crypto::decrypt(cipher_content, master_password)?;
+        if kdf_params.is_weaker_than(&KdfParams::MINIMUM) {
+            unlocked.wrap_key(
+                unlocked.key.clone(),
+                master_password,
+                kdf_params.strengthened(&KdfParams::MINIMUM),
+            )?;
+            unlocked.outdated = true;
+        }
 
-        let model = serde_json::from_str::<StoreModel>(&store_model_content)?;
+        Ok(unlocked)
+    }
+
+    /// Unlocks the DataStore through the agent holding it, without the master password.
+    ///
+    /// Changes of the DataStore are handed to the agent, which saves them.
+    ///
+    /// This will return an error if the agent cannot be reached.
+    pub fn unlock_with_agent(self, mut agent: AgentClient) -> Result<DataStore<Unlocked>> {
+        let data = agent.get()?;
 
         Ok(DataStore {
-            data: model.data,
+            data,
             state: PhantomData::<Unlocked>,
-            master_password: master_password.into(),
+            key: Default::default(),
+            key_slots: Default::default(),
+            path: self.path,
+            lock: None,
+            keyfile: self.keyfile,
+            outdated: false,
+            agent: Some(agent),
+            removed_blobs: vec![],
+            added_blobs: vec![],
         })
     }
-}
 
-/// Implementation for the `Unlocked` state of the DataStore.
-impl DataStore<Unlocked> {
-    /// Locks the DataStore and saves it to the file system.
+    /// Lists the encrypted backups of the DataStore, from the most recent to the oldest one.
+    ///
+    /// This will return an error if the backup files cannot be read.
+    pub fn backups(&self) -> Result<Vec<Backup>> {
+        storage::list_backups(&self.path)
+    }
+
+    /// Restores an encrypted backup of the DataStore given its version.
+    ///
+    /// The backup is unlocked with the given master password beforehand, so that
+    /// a corrupted backup never replaces the DataStore.
     ///
     /// This will return an error if :
-    /// - the content of the DataStore cannot be saved & crypted (various reasons).
-    pub fn lock(self) -> Result<DataStore<Locked>> {
-        let store_model = StoreModel { data: self.data };
+    /// - the backup cannot be found.
+    /// - the backup cannot be unlocked.
+    /// - the backup cannot be written over the DataStore.
+    pub fn restore_backup(&self, version: usize, master_password: &str) -> Result<()> {
+        let _lock = self.acquire_lock()?;
 
-        let store_model_content = serde_json::to_string(&store_model)?;
+        let backup = DataStore::new(storage::backup_path(&self.path, version)?)
+            .with_keyfile(self.keyfile.clone());
 
-        let cipher_content = crypto::encrypt(&store_model_content, &self.master_password)?;
+        if !backup.is_initialized()? {
+            bail!(StorageError::BackupNotFound(version));
+        }
 
-        let mid_cipher_content = serde_json::to_string(&cipher_content)?;
+        backup.unlock(master_password)?;
 
-        let mut b64_content = String::new();
+        storage::restore_backup(&self.path, version)
+    }
+}
+
+/// Implementation for the `Unlocked` state of the DataStore.
+impl DataStore<Unlocked> {
+    /// Marks the DataStore as modified, so that it is saved upon lock.
+    pub fn modify(self) -> DataStore<Dirty> {
+        self.into_state()
+    }
+
+    /// Destroys the current DataStore by removing it from the filesystem.
+    ///
+    /// This will return an error if :
+    /// - the DataStore file cannot be found in the filesystem.
+    /// - the DataStore file cannot be removed from the filesystem.
+    pub fn destroy(self) -> Result<()> {
+        fs::remove_file(&self.path)?;
 
-        general_purpose::STANDARD.encode_string(mid_cipher_content, &mut b64_content);
+        Ok(())
+    }
+}
 
-        fs::write(get_store_file_path()?, b64_content)?;
+/// Implementation for the `Dirty` state of the DataStore.
+impl DataStore<Dirty> {
+    /// Locks the DataStore and saves it to the file system (or to the agent holding it).
+    ///
+    /// This will return an error if :
+    /// - the content of the DataStore cannot be saved & crypted (various reasons).
+    pub fn lock(mut self) -> Result<DataStore<Locked>> {
+        if let Err(error) = self.persist() {
+            self.close();
+            bail!(error);
+        }
 
-        Ok(DataStore::default())
+        // the inter-process lock is only released once the DataStore is saved
+        Ok(self.close())
     }
 
-    /// Gets a reference to a given password dataset by its identifier.
+    /// Saves the DataStore to the file system, and keeps it unlocked.
     ///
-    /// This will return an error if the passsword dataset cannot be found.
-    pub fn get(&self, key: &str) -> Result<&PasswordStore> {
-        if !self.data.contains_key(key) {
-            bail!(DataStoreError::KeyNotFound(key.to_string()));
+    /// This will return an error if :
+    /// - the content of the DataStore cannot be saved & crypted (various reasons).
+    pub fn save(mut self) -> Result<DataStore<Unlocked>> {
+        if let Err(error) = self.persist() {
+            self.close();
+            bail!(error);
         }
 
-        Ok(self.data.get(key).unwrap())
+        Ok(self.into_state())
+    }
+
+    /// Saves the DataStore to the file system (or to the agent holding it), and keeps it
+    /// in the `Dirty` state for further changes.
+    ///
+    /// This will return an error if :
+    /// - the content of the DataStore cannot be saved & crypted (various reasons).
+    pub fn flush(&mut self) -> Result<()> {
+        self.persist()
+    }
+
+    /// Replaces all the password datasets of the DataStore.
+    pub fn replace_all(&mut self, data: HashMap<String, PasswordStore>) {
+        self.data = data;
     }
 
     /// Inserts a new password dataset into the DataStore.
@@ -150,44 +462,356 @@ impl DataStore<Unlocked> {
         Ok(())
     }
 
+    /// Updates an existing password dataset in the DataStore.
+    ///
+    /// If the password changes, the previous one is kept in the password history.
+    /// The blobs of the attachments removed from the dataset are deleted once the
+    /// DataStore is saved, and the ones of the added attachments if it is never saved.
+    ///
+    /// This will return an error if the identifier of this dataset cannot be found.
+    pub fn update(&mut self, store: &PasswordStore) -> Result<()> {
+        let label = store.label.clone();
+
+        let Some(current) = self.data.get(&label) else {
+            bail!(DataStoreError::KeyNotFound(label));
+        };
+
+        self.removed_blobs.extend(
+            current
+                .attachments
+                .iter()
+                .filter(|attachment| {
+                    !store
+                        .attachments
+                        .iter()
+                        .any(|kept| kept.blob == attachment.blob)
+                })
+                .map(|attachment| attachment.blob.clone()),
+        );
+        self.added_blobs.extend(
+            store
+                .attachments
+                .iter()
+                .filter(|attachment| {
+                    !current
+                        .attachments
+                        .iter()
+                        .any(|kept| kept.blob == attachment.blob)
+                })
+                .map(|attachment| attachment.blob.clone()),
+        );
+
+        let mut updated = PasswordStore {
+            password: current.password.clone(),
+            ..store.clone()
+        };
+        updated.change_password(store.password.expose());
+
+        self.data.insert(label, updated);
+
+        Ok(())
+    }
+
+    /// Restores a previous password of a password dataset given its version.
+    ///
+    /// This will return an error if :
+    /// - the passsword dataset cannot be found.
+    /// - the version is not found in the password history.
+    pub fn restore(&mut self, key: &str, version: usize) -> Result<()> {
+        let Some(store) = self.data.get_mut(key) else {
+            bail!(DataStoreError::KeyNotFound(key.to_string()));
+        };
+
+        store.restore_password(version)?;
+        store.modification_date = Some(Utc::now());
+
+        Ok(())
+    }
+
     /// deletes a password dataset from the DataStore.
     ///
+    /// The blobs of its attachments are deleted once the DataStore is saved.
+    ///
     /// This will return an error if the passsword dataset cannot be found.
     pub fn delete(&mut self, key: &str) -> Result<()> {
-        if !self.data.contains_key(key) {
+        let Some(store) = self.data.remove(key) else {
             bail!(DataStoreError::KeyNotFound(key.to_string()));
+        };
+
+        self.removed_blobs.extend(
+            store
+                .attachments
+                .into_iter()
+                .map(|attachment| attachment.blob),
+        );
+
+        Ok(())
+    }
+
+    /// Changes the master password of the DataStore.
+    ///
+    /// A fresh DataStore key is generated and wrapped in a new key slot, so that the
+    /// content is ciphered with it upon the next save: a key unwrapped with the
+    /// previous master password (e.g. from a backup) cannot decipher it anymore.
+    /// A keyfile used to unlock the DataStore is still required along the new master password.
+    ///
+    /// This will return an error if :
+    /// - the DataStore key cannot be wrapped.
+    /// - the new key slot cannot be verified.
+    pub fn change_master_password(&mut self, master_password: &str) -> Result<()> {
+        self.wrap_key(VaultKey::random(), master_password, self.kdf_params())
+    }
+
+    /// Changes the key derivation parameters of the master password key slot.
+    ///
+    /// The parameters are raised to `KdfParams::MINIMUM` if needed, and the ones
+    /// actually stored in the vault header are returned.
+    ///
+    /// This will return an error if :
+    /// - the DataStore key cannot be wrapped.
+    /// - the new key slot cannot be verified.
+    pub fn change_kdf_params(
+        &mut self,
+        master_password: &str,
+        kdf_params: KdfParams,
+    ) -> Result<KdfParams> {
+        let kdf_params = kdf_params.strengthened(&KdfParams::MINIMUM);
+
+        self.wrap_key(self.key.clone(), master_password, kdf_params)?;
+
+        Ok(kdf_params)
+    }
+
+    /// Writes the DataStore content to the file system, or hands it to the agent holding it,
+    /// then deletes the blobs of the attachments removed since the last save.
+    fn persist(&mut self) -> Result<()> {
+        match &mut self.agent {
+            Some(agent) => agent.put(&self.data)?,
+            None => self.write_vault()?,
         }
 
-        self.data.remove(key);
+        self.added_blobs.clear();
+        self.remove_blobs();
 
         Ok(())
     }
 
-    /// returns the actual Map of password dataset.
-    pub fn data(&self) -> HashMap<String, PasswordStore> {
-        self.data.clone()
+    /// Deletes the blobs of the attachments removed since the last save.
+    ///
+    /// This is done on a best effort basis, as the DataStore is already saved: a blob
+    /// left behind is never read again. Backups of the DataStore may still reference
+    /// deleted blobs, whose attachments cannot be extracted anymore.
+    fn remove_blobs(&mut self) {
+        let Ok(blobs_folder) = attachment::blobs_folder(&self.path) else {
+            return;
+        };
+
+        for blob in self.removed_blobs.drain(..) {
+            let _ = fs::remove_file(blobs_folder.join(blob));
+        }
     }
 
-    /// Destroys the current DataStore by removing it from the filesystem.
+    /// Writes the DataStore content to the file system.
+    ///
+    /// The DataStore is always saved in the current vault file format, so that
+    /// a DataStore in an older format is migrated on its first save.
+    ///
+    /// The content is ciphered with the DataStore key, so no key derivation happens here.
+    fn write_vault(&mut self) -> Result<()> {
+        let store_model = StoreModel {
+            data: std::mem::take(&mut self.data),
+        };
+        let store_model_content = serde_json::to_vec(&store_model);
+        self.data = store_model.data;
+
+        let store_model_content = Zeroizing::new(store_model_content?);
+
+        let vault_content = vault_format::seal(&store_model_content, &self.key, &self.key_slots)?;
+
+        // the ciphered content must be proven readable before replacing the DataStore
+        match vault_format::open_with_key(&vault_content, &self.key) {
+            Ok(content) if content == store_model_content => {}
+            _ => bail!(DataStoreError::SaveVerificationFailed),
+        }
+
+        storage::atomic_write(&self.path, &vault_content)
+    }
+}
+
+/// Implementation for the readable states of the DataStore.
+impl<State: Opened> DataStore<State> {
+    /// Gets a reference to a given password dataset by its identifier.
+    ///
+    /// This will return an error if the passsword dataset cannot be found.
+    pub fn get(&self, key: &str) -> Result<&PasswordStore> {
+        if !self.data.contains_key(key) {
+            bail!(DataStoreError::KeyNotFound(key.to_string()));
+        }
+
+        Ok(self.data.get(key).unwrap())
+    }
+
+    /// Searches the password datasets matching a query, the best matches first.
+    ///
+    /// See `PasswordStore::search_score` for the matching of the query, and
+    /// password datasets matching equally are sorted by label.
+    pub fn search(&self, query: &str) -> Vec<&PasswordStore> {
+        self.data
+            .values()
+            .filter_map(|store| Some((store.search_score(query)?, store)))
+            .sorted_by(|(score, store), (other_score, other)| {
+                other_score
+                    .cmp(score)
+                    .then_with(|| store.label.cmp(&other.label))
+            })
+            .map(|(_, store)| store)
+            .collect()
+    }
+
+    /// Lists the password datasets expired, or expiring within a number of days,
+    /// the ones expiring first being listed first.
+    pub fn expiring(&self, today: NaiveDate, within_days: i64) -> Vec<&PasswordStore> {
+        self.data
+            .values()
+            .filter(|store| {
+                store
+                    .days_before_expiration(today)
+                    .is_some_and(|days| days <= within_days)
+            })
+            .sorted_by(|store, other| {
+                store
+                    .expiration_date
+                    .cmp(&other.expiration_date)
+                    .then_with(|| store.label.cmp(&other.label))
+            })
+            .collect()
+    }
+
+    /// returns a reference to the actual Map of password dataset.
+    pub fn data(&self) -> &HashMap<String, PasswordStore> {
+        &self.data
+    }
+
+    /// Checks either the DataStore must be saved again to be upgraded.
+    ///
+    /// This is the case when it has been loaded from an older vault file format,
+    /// or when its key has been wrapped again with stronger key derivation parameters.
+    pub fn is_outdated(&self) -> bool {
+        self.outdated
+    }
+
+    /// Closes the DataStore without writing it to the filesystem.
+    ///
+    /// Any unsaved change is discarded, along the blobs of the attachments added since
+    /// the last save, and the inter-process lock is released.
+    pub fn close(mut self) -> DataStore<Locked> {
+        self.discard_blobs();
+        drop(self.lock);
+
+        DataStore::new(self.path).with_keyfile(self.keyfile)
+    }
+
+    /// Deletes the blobs of the attachments added since the last save, which are only
+    /// referenced by unsaved changes, on a best effort basis.
+    fn discard_blobs(&mut self) {
+        let Ok(blobs_folder) = attachment::blobs_folder(&self.path) else {
+            return;
+        };
+
+        for blob in self.added_blobs.drain(..) {
+            let _ = fs::remove_file(blobs_folder.join(blob));
+        }
+    }
+
+    /// returns the key derivation parameters of the master password key slot.
+    fn kdf_params(&self) -> KdfParams {
+        self.key_slots
+            .iter()
+            .find(|slot| slot.is_master_password())
+            .map(|slot| slot.kdf_params)
+            .unwrap_or_default()
+    }
+
+    /// Wraps a DataStore key with a master password, given key derivation parameters,
+    /// and makes it the key of the DataStore.
+    ///
+    /// A keyfile used to unlock the DataStore is still required along the master password.
+    /// The key and the master password key slots are only replaced once the new key
+    /// slot has been unwrapped with the master password.
     ///
     /// This will return an error if :
-    /// - the DataStore file cannot be found in the filesystem.
-    /// - the DataStore file cannot be removed from the filesystem.
-    pub fn destroy(self) -> Result<()> {
-        fs::remove_file(get_store_file_path()?)?;
+    /// - the DataStore key cannot be wrapped.
+    /// - the new key slot cannot be verified.
+    fn wrap_key(
+        &mut self,
+        key: VaultKey,
+        master_password: &str,
+        kdf_params: KdfParams,
+    ) -> Result<()> {
+        // the key is only known by the agent holding the DataStore
+        if self.agent.is_some() {
+            bail!(DataStoreError::HeldByAgent);
+        }
+
+        let keyfile = self.keyfile.as_ref();
+
+        let slot = KeySlot::wrap(&key, master_password, keyfile, kdf_params)?;
+
+        match slot.unwrap(master_password, keyfile) {
+            Ok(unwrapped) if unwrapped == key => {}
+            _ => bail!(DataStoreError::MasterPasswordVerificationFailed),
+        }
+
+        // every key slot holds the same key, so none is kept when the key changes
+        self.key_slots.clear();
+        self.key_slots.push(slot);
+        self.key = key;
 
         Ok(())
     }
 }
 
+/// Implementation for any state of the DataStore.
+impl<State> DataStore<State> {
+    /// returns the path of the DataStore file in the filesystem.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the DataStore into another state, keeping its content.
+    fn into_state<Next>(self) -> DataStore<Next> {
+        DataStore {
+            data: self.data,
+            state: PhantomData::<Next>,
+            key: self.key,
+            key_slots: self.key_slots,
+            path: self.path,
+            lock: self.lock,
+            keyfile: self.keyfile,
+            outdated: self.outdated,
+            agent: self.agent,
+            removed_blobs: self.removed_blobs,
+            added_blobs: self.added_blobs,
+        }
+    }
+}
+
 /// Default implementation of the DataStore.
 impl DataStore {
-    /// Initializes a DataStore instance to be operated on.
-    pub fn new() -> DataStore<Locked> {
+    /// Initializes a DataStore instance to be operated on, given the path of its file.
+    pub fn new(path: PathBuf) -> DataStore<Locked> {
         Self {
             data: Default::default(),
             state: Default::default(),
-            master_password: Default::default(),
+            key: Default::default(),
+            key_slots: Default::default(),
+            path,
+            lock: None,
+            keyfile: None,
+            outdated: false,
+            agent: None,
+            removed_blobs: vec![],
+            added_blobs: vec![],
         }
     }
 
@@ -196,9 +820,7 @@ impl DataStore {
     /// This will return an error if :
     /// - the DataStore cannot be found in the filesystem.
     pub fn is_initialized(&self) -> Result<bool> {
-        let datastore_path = get_store_file_path()?;
-
-        let exists = datastore_path.try_exists();
+        let exists = self.path.try_exists();
 
         if let Err(error) = exists {
             bail!(error);
@@ -207,32 +829,66 @@ impl DataStore {
         Ok(exists.unwrap())
     }
 
-    /// Initializes the DataStore in the filesystem.
+    /// Initializes the DataStore in the filesystem, and returns it unlocked & modified.
+    ///
+    /// The DataStore key is created and wrapped by the master password, and the
+    /// DataStore file is written upon lock. As the key is already known,
+    /// the DataStore does not need to be unlocked again (e.g. a second key derivation).
     ///
     /// This will return an error if :
-    /// - the DataStore file cannot be created.
-    pub fn initialize(&self, master_password: &str) -> Result<DataStore<Locked>> {
-        let store_folder = get_store_folder_path()?;
-
-        if !store_folder.exists() {
-            fs::create_dir(store_folder)?;
+    /// - the DataStore folder cannot be created.
+    /// - the DataStore key cannot be created.
+    /// - the DataStore is in use by another process.
+    pub fn initialize(&self, master_password: &str) -> Result<DataStore<Dirty>> {
+        if let Some(store_folder) = self.path.parent() {
+            if !store_folder.as_os_str().is_empty() && !store_folder.exists() {
+                fs::create_dir_all(store_folder)?;
+            }
         }
 
-        let store_file = get_store_file_path()?;
+        let (key, slot) =
+            vault_format::create_key(master_password, self.keyfile.as_ref(), KdfParams::default())?;
 
-        if !store_file.exists() {
-            fs::write(get_store_file_path()?, "")?
-        }
-
-        let mock = DataStore {
+        Ok(DataStore {
             data: Default::default(),
-            state: PhantomData::<Unlocked>,
-            master_password: master_password.into(),
-        };
+            state: PhantomData::<Dirty>,
+            key,
+            key_slots: vec![slot],
+            path: self.path.clone(),
+            lock: Some(self.acquire_lock()?),
+            keyfile: self.keyfile.clone(),
+            outdated: false,
+            agent: None,
+            removed_blobs: vec![],
+            added_blobs: vec![],
+        })
+    }
 
-        let locked = mock.lock()?;
+    /// Acquires the inter-process lock of the DataStore.
+    ///
+    /// If another rpass process holds the lock, this waits for it to be released
+    /// for `RPASS_LOCK_TIMEOUT_SECS` at most.
+    ///
+    /// This will return an error if :
+    /// - the lock is still held by another process after the timeout.
+    /// - the lock file cannot be created.
+    fn acquire_lock(&self) -> Result<LockFile> {
+        let deadline = Instant::now() + Duration::from_secs(RPASS_LOCK_TIMEOUT_SECS);
+
+        loop {
+            if let Some(lock) = LockFile::try_acquire(&self.path)? {
+                return Ok(lock);
+            }
 
-        Ok(locked)
+            if Instant::now() >= deadline {
+                bail!(DataStoreError::Locked {
+                    pid: LockFile::holder(&self.path)?,
+                    lock_path: LockFile::path_for(&self.path)?,
+                });
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
     }
 
     /// Loads the content of the DataStore file.
@@ -240,7 +896,358 @@ impl DataStore {
     /// This will return an error if :
     /// - the DataStore file cannot be found in the filesystem.
     /// - the DataStore file cannot be read.
-    fn load_content(&self) -> Result<String> {
-        Ok(fs::read_to_string(get_store_file_path()?)?)
+    fn load_content(&self) -> Result<Vec<u8>> {
+        Ok(fs::read(&self.path)?)
+    }
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFolder;
+
+    /// creates a password dataset for tests
+    fn password_store(password: &str) -> PasswordStore {
+        PasswordStore::new("test", password.into())
+    }
+
+    /// test function for the password history upon password change
+    #[test]
+    fn assert_password_history_is_kept() {
+        let mut store = password_store("first");
+
+        store.change_password("first");
+        assert!(store.history.is_empty());
+
+        store.change_password("second");
+        store.change_password("third");
+
+        assert_eq!("third", store.password.expose());
+        assert_eq!(
+            vec!["second", "first"],
+            store
+                .history
+                .iter()
+                .map(|h| h.password.expose())
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    /// test function for the password history size
+    #[test]
+    fn assert_password_history_is_bounded() {
+        let mut store = password_store("0");
+
+        for i in 1..=RPASS_PASSWORD_HISTORY_SIZE + 5 {
+            store.change_password(&i.to_string());
+        }
+
+        assert_eq!(RPASS_PASSWORD_HISTORY_SIZE, store.history.len());
+        assert_eq!("5", store.history.last().unwrap().password.expose());
+    }
+
+    /// test function for password restoration
+    #[test]
+    fn assert_password_restore() {
+        let mut store = password_store("first");
+        store.change_password("second");
+        store.change_password("third");
+
+        store.restore_password(2).unwrap();
+
+        assert_eq!("first", store.password.expose());
+        assert_eq!("third", store.history[0].password.expose());
+        assert_eq!("second", store.history[1].password.expose());
+        assert!(store.restore_password(0).is_err());
+        assert!(store.restore_password(3).is_err());
+    }
+
+    /// test function for the search of password datasets
+    #[test]
+    fn assert_search_matches_every_field() {
+        let github = PasswordStore {
+            label: "github".into(),
+            ..password_store("first")
+        };
+        let mirror = PasswordStore {
+            label: "mirror".into(),
+            url: Some("https://git.example.com".into()),
+            ..password_store("first")
+        };
+        let bank = PasswordStore {
+            label: "bank".into(),
+            login: Some("jdoe".into()),
+            ..password_store("first")
+        };
+
+        assert!(github.search_score("git").unwrap() > mirror.search_score("git").unwrap());
+        assert!(bank.search_score("git").is_none());
+        assert!(bank.search_score("JDOE").is_some());
+
+        let api = PasswordStore {
+            label: "api".into(),
+            fields: vec![
+                CustomField {
+                    name: "account id".into(),
+                    value: "4815162342".into(),
+                    secret: false,
+                },
+                CustomField {
+                    name: "token".into(),
+                    value: "sk-hidden".into(),
+                    secret: true,
+                },
+            ],
+            ..password_store("first")
+        };
+
+        assert!(api.search_score("4815").is_some());
+        assert!(api.search_score("token").is_some());
+        assert!(api.search_score("hidden").is_none());
+        assert_eq!("sk-hidden", api.field("token").unwrap().value.expose());
+        assert!(api.field("pin").is_none());
+    }
+
+    /// test function for the listing of expired and expiring password datasets
+    #[test]
+    fn assert_expiring_passwords_are_listed() {
+        let today = NaiveDate::from_ymd_opt(2023, 6, 15).unwrap();
+
+        let mut modified = DataStore::<Dirty>::default();
+
+        for (label, expiration_date) in [
+            ("never", None),
+            ("expired", NaiveDate::from_ymd_opt(2023, 6, 1)),
+            ("today", Some(today)),
+            ("soon", NaiveDate::from_ymd_opt(2023, 7, 1)),
+            ("later", NaiveDate::from_ymd_opt(2023, 12, 31)),
+        ] {
+            modified
+                .insert(&PasswordStore {
+                    label: label.into(),
+                    expiration_date,
+                    ..password_store("first")
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            Some(16),
+            modified.get("soon").unwrap().days_before_expiration(today)
+        );
+        assert_eq!(
+            Some(0),
+            modified.get("today").unwrap().days_before_expiration(today)
+        );
+        assert_eq!(
+            None,
+            modified.get("never").unwrap().days_before_expiration(today)
+        );
+
+        let labels = |within_days| {
+            modified
+                .expiring(today, within_days)
+                .iter()
+                .map(|store| store.label.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["expired", "today"], labels(0));
+        assert_eq!(vec!["expired", "today", "soon"], labels(30));
+        assert_eq!(vec!["expired", "today", "soon", "later"], labels(365));
+    }
+
+    /// test function for the DataStore being only written once modified
+    #[test]
+    fn assert_closed_datastore_is_not_written() {
+        let folder = TempFolder::new("close");
+        let path = folder.join(".datastore");
+
+        let mut modified = DataStore::new(path.clone()).initialize("master").unwrap();
+        modified.insert(&password_store("first")).unwrap();
+        modified.lock().unwrap();
+
+        let content = fs::read(&path).unwrap();
+
+        let opened = DataStore::new(path.clone()).unlock("master").unwrap();
+        assert!(!opened.is_outdated());
+        assert_eq!("first", opened.get("test").unwrap().password.expose());
+
+        let mut modified = opened.modify();
+        modified.delete("test").unwrap();
+        modified.close();
+
+        assert_eq!(content, fs::read(&path).unwrap());
+        assert!(!storage::backup_path(&path, 1).unwrap().exists());
+        assert!(!LockFile::path_for(&path).unwrap().exists());
+    }
+
+    /// test function for the deletion of the blobs of removed attachments, once saved
+    #[test]
+    fn assert_removed_blobs_are_deleted() {
+        let folder = TempFolder::new("blobs");
+        let path = folder.join(".datastore");
+        let blobs_folder = attachment::blobs_folder(&path).unwrap();
+
+        fs::write(folder.join("file"), b"content").unwrap();
+
+        let attach =
+            |name: &str| Attachment::create(&blobs_folder, name, &folder.join("file")).unwrap();
+        let (first, second) = (attach("first"), attach("second"));
+        let blob_exists = |attachment: &Attachment| blobs_folder.join(&attachment.blob).exists();
+
+        let mut modified = DataStore::new(path.clone()).initialize("master").unwrap();
+        modified
+            .insert(&PasswordStore {
+                attachments: vec![first.clone(), second.clone()],
+                ..password_store("first")
+            })
+            .unwrap();
+        modified.lock().unwrap();
+
+        let mut modified = DataStore::new(path.clone())
+            .unlock("master")
+            .unwrap()
+            .modify();
+
+        // blobs are kept until the DataStore is saved
+        modified
+            .update(&PasswordStore {
+                attachments: vec![second.clone()],
+                ..password_store("first")
+            })
+            .unwrap();
+        modified.close();
+        assert!(blob_exists(&first));
+
+        let mut modified = DataStore::new(path.clone())
+            .unlock("master")
+            .unwrap()
+            .modify();
+        modified
+            .update(&PasswordStore {
+                attachments: vec![second.clone()],
+                ..password_store("first")
+            })
+            .unwrap();
+        modified.flush().unwrap();
+        assert!(!blob_exists(&first));
+        assert!(blob_exists(&second));
+
+        modified.delete("test").unwrap();
+        modified.lock().unwrap();
+        assert!(!blob_exists(&second));
+    }
+
+    /// test function for the deletion of the blobs of added attachments, if never saved
+    #[test]
+    fn assert_unsaved_blobs_are_deleted() {
+        let folder = TempFolder::new("unsaved");
+        let path = folder.join(".datastore");
+        let blobs_folder = attachment::blobs_folder(&path).unwrap();
+
+        fs::write(folder.join("file"), b"content").unwrap();
+
+        let attach =
+            |name: &str| Attachment::create(&blobs_folder, name, &folder.join("file")).unwrap();
+        let blob_exists = |attachment: &Attachment| blobs_folder.join(&attachment.blob).exists();
+
+        let mut modified = DataStore::new(path.clone()).initialize("master").unwrap();
+        modified.insert(&password_store("first")).unwrap();
+        modified.lock().unwrap();
+
+        let unlock = || {
+            DataStore::new(path.clone())
+                .unlock("master")
+                .unwrap()
+                .modify()
+        };
+
+        let (closed, failed, saved) = (attach("closed"), attach("failed"), attach("saved"));
+        let add = |modified: &mut DataStore<Dirty>, attachment: &Attachment| {
+            modified
+                .update(&PasswordStore {
+                    attachments: vec![attachment.clone()],
+                    ..password_store("first")
+                })
+                .unwrap();
+        };
+
+        let mut modified = unlock();
+        add(&mut modified, &closed);
+        modified.close();
+        assert!(!blob_exists(&closed));
+
+        // the DataStore file cannot be replaced by a folder
+        let mut modified = unlock();
+        add(&mut modified, &failed);
+        fs::rename(&path, folder.join("moved")).unwrap();
+        fs::create_dir_all(path.join("folder")).unwrap();
+        assert!(modified.lock().is_err());
+        assert!(!blob_exists(&failed));
+
+        fs::remove_dir_all(&path).unwrap();
+        fs::rename(folder.join("moved"), &path).unwrap();
+
+        let mut modified = unlock();
+        add(&mut modified, &saved);
+        modified.flush().unwrap();
+        modified.close();
+        assert!(blob_exists(&saved));
+    }
+
+    /// test function for the change of the master password, rotating the DataStore key
+    #[test]
+    fn assert_master_password_change_rotates_key() {
+        let folder = TempFolder::new("change");
+        let path = folder.join(".datastore");
+
+        let mut modified = DataStore::new(path.clone()).initialize("master").unwrap();
+        modified.insert(&password_store("first")).unwrap();
+        modified.lock().unwrap();
+
+        let previous = vault_format::open(&fs::read(&path).unwrap(), "master", None).unwrap();
+
+        let mut modified = DataStore::new(path.clone())
+            .unlock("master")
+            .unwrap()
+            .modify();
+        modified.change_master_password("new master").unwrap();
+        modified.lock().unwrap();
+
+        let vault = fs::read(&path).unwrap();
+
+        assert!(DataStore::new(path.clone()).unlock("master").is_err());
+        assert!(vault_format::open_with_key(&vault, &previous.key).is_err());
+
+        let opened = DataStore::new(path.clone()).unlock("new master").unwrap();
+        assert_eq!("first", opened.get("test").unwrap().password.expose());
+        opened.close();
+    }
+
+    /// test function for the rehash of a DataStore key derived with weak parameters
+    #[test]
+    fn assert_weak_key_derivation_is_upgraded() {
+        let folder = TempFolder::new("rehash");
+        let path = folder.join(".datastore");
+
+        let weak_params = KdfParams {
+            mem_cost: 8,
+            time_cost: 1,
+            lanes: 1,
+        };
+        let (key, slot) = vault_format::create_key("master", None, weak_params).unwrap();
+        let content = serde_json::to_vec(&StoreModel::default()).unwrap();
+        fs::write(&path, vault_format::seal(&content, &key, &[slot]).unwrap()).unwrap();
+
+        let opened = DataStore::new(path.clone()).unlock("master").unwrap();
+        assert!(opened.is_outdated());
+        assert!(!opened.kdf_params().is_weaker_than(&KdfParams::MINIMUM));
+        opened.modify().lock().unwrap();
+
+        let opened = DataStore::new(path.clone()).unlock("master").unwrap();
+        assert!(!opened.is_outdated());
+        opened.close();
     }
 }
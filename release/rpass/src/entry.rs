@@ -0,0 +1,495 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::secret::SecretString;
+
+/// Possible errors upon the validation of entry fields.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EntryError {
+    #[error("Card number must have 12 to 19 digits")]
+    InvalidCardNumberLength,
+    #[error("Card number is not valid, check its digits")]
+    InvalidCardNumber,
+    #[error("Card expiry must be formatted as MM/YY")]
+    InvalidCardExpiry,
+    #[error("Security code must have 3 or 4 digits")]
+    InvalidSecurityCode,
+    #[error("Email address is not valid")]
+    InvalidEmail,
+    #[error("SSID must have 1 to 32 bytes")]
+    InvalidSsid,
+    #[error("{0} keys must have {1}")]
+    InvalidWifiKey(WifiSecurity, &'static str),
+    #[error("{0} is required")]
+    EmptySecret(&'static str),
+}
+
+/// Kinds of entries, each one with its own fields.
+///
+/// Every entry has a main secret, stored as its password: the password of a login,
+/// the content of a note, the security code of a card, the document number of an
+/// identity, or the key of a Wi-Fi network.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EntryKind {
+    /// A login on a website or an application.
+    #[default]
+    Login,
+    /// A secure note (e.g. recovery codes).
+    SecureNote,
+    /// A payment card.
+    Card(CardDetails),
+    /// An identity (e.g. an ID card or a passport).
+    Identity(IdentityDetails),
+    /// A Wi-Fi network.
+    Wifi(WifiDetails),
+}
+
+/// Fields of a payment card.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CardDetails {
+    /// The name printed on the card.
+    pub cardholder: String,
+    /// The card number, digits only.
+    pub number: SecretString,
+    /// The expiry of the card, formatted as MM/YY.
+    pub expiry: String,
+}
+
+/// Fields of an identity.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IdentityDetails {
+    /// The full name of the person.
+    pub full_name: String,
+    /// The birth date of the person (optionnal).
+    pub birth_date: Option<NaiveDate>,
+    /// The email address of the person (optionnal).
+    pub email: Option<String>,
+    /// The phone number of the person (optionnal).
+    pub phone: Option<String>,
+    /// The postal address of the person (optionnal).
+    pub address: Option<String>,
+}
+
+/// Fields of a Wi-Fi network.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WifiDetails {
+    /// The name of the network.
+    pub ssid: String,
+    /// The security protocol of the network.
+    pub security: WifiSecurity,
+}
+
+/// Security protocols of Wi-Fi networks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WifiSecurity {
+    /// WPA, WPA2 or WPA3 personal.
+    #[default]
+    Wpa,
+    /// WEP (deprecated).
+    Wep,
+    /// An open network, without any key.
+    Open,
+}
+
+impl EntryKind {
+    /// returns an empty entry of each kind.
+    pub fn all() -> [EntryKind; 5] {
+        [
+            EntryKind::Login,
+            EntryKind::SecureNote,
+            EntryKind::Card(CardDetails::default()),
+            EntryKind::Identity(IdentityDetails::default()),
+            EntryKind::Wifi(WifiDetails::default()),
+        ]
+    }
+
+    /// returns the name of the kind of entry.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Login => "Login",
+            EntryKind::SecureNote => "Secure note",
+            EntryKind::Card(_) => "Payment card",
+            EntryKind::Identity(_) => "Identity",
+            EntryKind::Wifi(_) => "Wi-Fi network",
+        }
+    }
+
+    /// returns the name of the main secret of the entry, stored as its password.
+    pub fn secret_name(&self) -> &'static str {
+        match self {
+            EntryKind::Login => "Password",
+            EntryKind::SecureNote => "Note",
+            EntryKind::Card(_) => "Security code",
+            EntryKind::Identity(_) => "Document number",
+            EntryKind::Wifi(_) => "Key",
+        }
+    }
+
+    /// returns either the entry has a main secret, only open Wi-Fi networks having none.
+    pub fn has_secret(&self) -> bool {
+        !matches!(
+            self,
+            EntryKind::Wifi(WifiDetails {
+                security: WifiSecurity::Open,
+                ..
+            })
+        )
+    }
+
+    /// returns either the main secret is required, an identity having none by default.
+    pub fn requires_secret(&self) -> bool {
+        self.has_secret() && !matches!(self, EntryKind::Identity(_))
+    }
+
+    /// returns either the strength of the main secret matters (e.g. a password, but not a security code).
+    pub fn checks_strength(&self) -> bool {
+        matches!(self, EntryKind::Login)
+            || (self.has_secret() && matches!(self, EntryKind::Wifi(_)))
+    }
+
+    /// Checks the main secret of the entry is valid for its kind.
+    ///
+    /// This will return an error if :
+    /// - the secret is required but empty.
+    /// - a security code is not made of 3 or 4 digits.
+    /// - a Wi-Fi key does not fit the security protocol of the network.
+    pub fn validate_secret(&self, secret: &str) -> Result<(), EntryError> {
+        if secret.trim().is_empty() && self.requires_secret() {
+            return Err(EntryError::EmptySecret(self.secret_name()));
+        }
+
+        match self {
+            EntryKind::Card(_) => validate_security_code(secret),
+            EntryKind::Wifi(wifi) => validate_wifi_key(wifi.security, secret),
+            _ => Ok(()),
+        }
+    }
+
+    /// returns the fields specific to the kind of entry, as (name, value), secrets being masked.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        match self {
+            EntryKind::Login | EntryKind::SecureNote => vec![],
+            EntryKind::Card(card) => vec![
+                ("Cardholder", card.cardholder.clone()),
+                ("Card number", mask_card_number(card.number.expose())),
+                ("Expiry", card.expiry.clone()),
+            ],
+            EntryKind::Identity(identity) => vec![
+                ("Full name", identity.full_name.clone()),
+                (
+                    "Birth date",
+                    identity
+                        .birth_date
+                        .map(|date| date.format("%v").to_string())
+                        .unwrap_or_default(),
+                ),
+                ("Email", identity.email.clone().unwrap_or_default()),
+                ("Phone", identity.phone.clone().unwrap_or_default()),
+                ("Address", identity.address.clone().unwrap_or_default()),
+            ],
+            EntryKind::Wifi(wifi) => vec![
+                ("SSID", wifi.ssid.clone()),
+                ("Security", wifi.security.to_string()),
+            ],
+        }
+    }
+
+    /// returns a one-line summary of the fields specific to the kind of entry.
+    pub fn summary(&self) -> String {
+        match self {
+            EntryKind::Login | EntryKind::SecureNote => String::new(),
+            EntryKind::Card(card) => format!(
+                "{}, expires {}",
+                mask_card_number(card.number.expose()),
+                card.expiry
+            ),
+            EntryKind::Identity(identity) => identity.full_name.clone(),
+            EntryKind::Wifi(wifi) => format!("{} ({})", wifi.ssid, wifi.security),
+        }
+    }
+
+    /// returns the fields specific to the kind of entry which can be searched.
+    pub fn searchable_fields(&self) -> Vec<&str> {
+        match self {
+            EntryKind::Login | EntryKind::SecureNote => vec![],
+            EntryKind::Card(card) => vec![&card.cardholder],
+            EntryKind::Identity(identity) => [&identity.email, &identity.phone]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .chain([identity.full_name.as_str()])
+                .collect(),
+            EntryKind::Wifi(wifi) => vec![&wifi.ssid],
+        }
+    }
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl WifiSecurity {
+    /// returns every security protocol.
+    pub fn all() -> [WifiSecurity; 3] {
+        [WifiSecurity::Wpa, WifiSecurity::Wep, WifiSecurity::Open]
+    }
+}
+
+impl fmt::Display for WifiSecurity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WifiSecurity::Wpa => "WPA",
+            WifiSecurity::Wep => "WEP",
+            WifiSecurity::Open => "Open",
+        })
+    }
+}
+
+/// returns the digits of a card number, without the spaces and dashes separating them.
+pub fn normalize_card_number(number: &str) -> String {
+    number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect()
+}
+
+/// Checks a card number has a valid length and checksum (Luhn algorithm).
+///
+/// Spaces and dashes separating the digits are ignored.
+///
+/// This will return an error if :
+/// - the number has anything else than 12 to 19 digits.
+/// - the checksum of the number is wrong.
+pub fn validate_card_number(number: &str) -> Result<(), EntryError> {
+    let number = normalize_card_number(number);
+
+    if !(12..=19).contains(&number.len()) || !number.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(EntryError::InvalidCardNumberLength);
+    }
+
+    // every second digit from the right is doubled, the sum must be a multiple of 10
+    let sum: u32 = number
+        .bytes()
+        .rev()
+        .map(|c| u32::from(c - b'0'))
+        .enumerate()
+        .map(|(index, digit)| match index % 2 {
+            1 if digit > 4 => digit * 2 - 9,
+            1 => digit * 2,
+            _ => digit,
+        })
+        .sum();
+
+    if !sum.is_multiple_of(10) {
+        return Err(EntryError::InvalidCardNumber);
+    }
+
+    Ok(())
+}
+
+/// Checks a card expiry is formatted as MM/YY.
+///
+/// This will return an error if the expiry is malformed, or its month does not exist.
+pub fn validate_card_expiry(expiry: &str) -> Result<(), EntryError> {
+    let is_valid = expiry.split_once('/').is_some_and(|(month, year)| {
+        month.len() == 2
+            && year.len() == 2
+            && expiry
+                .bytes()
+                .filter(|c| *c != b'/')
+                .all(|c| c.is_ascii_digit())
+            && month
+                .parse::<u32>()
+                .is_ok_and(|month| (1..=12).contains(&month))
+    });
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(EntryError::InvalidCardExpiry),
+    }
+}
+
+/// Checks a security code (e.g. CVV) has 3 or 4 digits.
+///
+/// This will return an error if the code has anything else than 3 or 4 digits.
+pub fn validate_security_code(code: &str) -> Result<(), EntryError> {
+    match (3..=4).contains(&code.len()) && code.bytes().all(|c| c.is_ascii_digit()) {
+        true => Ok(()),
+        false => Err(EntryError::InvalidSecurityCode),
+    }
+}
+
+/// Checks an optional email address looks valid (e.g. `name@domain.tld`).
+///
+/// This will return an error if the address is not empty and malformed.
+pub fn validate_email(email: &str) -> Result<(), EntryError> {
+    if email.is_empty() {
+        return Ok(());
+    }
+
+    let is_valid = email.split_once('@').is_some_and(|(name, domain)| {
+        !name.is_empty()
+            && !email.contains(char::is_whitespace)
+            && domain
+                .split_once('.')
+                .is_some_and(|(host, tld)| !host.is_empty() && !tld.is_empty())
+    });
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(EntryError::InvalidEmail),
+    }
+}
+
+/// Checks the name of a Wi-Fi network has 1 to 32 bytes.
+///
+/// This will return an error if the name is empty or too long.
+pub fn validate_ssid(ssid: &str) -> Result<(), EntryError> {
+    match (1..=32).contains(&ssid.len()) {
+        true => Ok(()),
+        false => Err(EntryError::InvalidSsid),
+    }
+}
+
+/// Checks a Wi-Fi key fits the security protocol of its network:
+/// - WPA: 8 to 63 characters, or 64 hexadecimal digits.
+/// - WEP: 5 or 13 characters, or 10 or 26 hexadecimal digits.
+/// - Open: no key.
+///
+/// This will return an error if the key does not fit the security protocol.
+pub fn validate_wifi_key(security: WifiSecurity, key: &str) -> Result<(), EntryError> {
+    let is_hex = key.bytes().all(|c| c.is_ascii_hexdigit());
+    let length = key.chars().count();
+
+    let (is_valid, expected) = match security {
+        WifiSecurity::Wpa => (
+            (8..=63).contains(&length) || (length == 64 && is_hex),
+            "8 to 63 characters, or 64 hexadecimal digits",
+        ),
+        WifiSecurity::Wep => (
+            [5, 13].contains(&length) || ([10, 26].contains(&length) && is_hex),
+            "5 or 13 characters, or 10 or 26 hexadecimal digits",
+        ),
+        WifiSecurity::Open => (key.is_empty(), "no characters"),
+    };
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(EntryError::InvalidWifiKey(security, expected)),
+    }
+}
+
+/// Masks a card number, but its last 4 digits.
+fn mask_card_number(number: &str) -> String {
+    let last_digits: String = number
+        .chars()
+        .skip(number.chars().count().saturating_sub(4))
+        .collect();
+
+    format!("•••• {last_digits}")
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the validation of card numbers, expiries and security codes
+    #[test]
+    fn assert_cards_are_validated() {
+        assert_eq!(Ok(()), validate_card_number("4242 4242 4242 4242"));
+        assert_eq!(Ok(()), validate_card_number("5555-5555-5555-4444"));
+        assert_eq!(Ok(()), validate_card_number("378282246310005"));
+        assert_eq!(
+            Err(EntryError::InvalidCardNumber),
+            validate_card_number("4242 4242 4242 4241")
+        );
+        assert_eq!(
+            Err(EntryError::InvalidCardNumberLength),
+            validate_card_number("4242")
+        );
+        assert_eq!(
+            Err(EntryError::InvalidCardNumberLength),
+            validate_card_number("4242 4242 4242 424a")
+        );
+
+        assert_eq!(Ok(()), validate_card_expiry("09/27"));
+        assert!(validate_card_expiry("13/27").is_err());
+        assert!(validate_card_expiry("9/27").is_err());
+        assert!(validate_card_expiry("09/2027").is_err());
+        assert!(validate_card_expiry("+9/27").is_err());
+
+        assert_eq!(Ok(()), validate_security_code("123"));
+        assert_eq!(Ok(()), validate_security_code("1234"));
+        assert!(validate_security_code("12").is_err());
+        assert!(validate_security_code("12a").is_err());
+    }
+
+    /// test function for the validation of Wi-Fi networks and identities
+    #[test]
+    fn assert_fields_are_validated() {
+        assert_eq!(
+            Ok(()),
+            validate_wifi_key(WifiSecurity::Wpa, "correct horse")
+        );
+        assert_eq!(
+            Ok(()),
+            validate_wifi_key(WifiSecurity::Wpa, &"a1".repeat(32))
+        );
+        assert!(validate_wifi_key(WifiSecurity::Wpa, "short").is_err());
+        assert!(validate_wifi_key(WifiSecurity::Wpa, &"z".repeat(64)).is_err());
+        assert_eq!(Ok(()), validate_wifi_key(WifiSecurity::Wep, "0123456789"));
+        assert!(validate_wifi_key(WifiSecurity::Wep, "012345678").is_err());
+        assert_eq!(Ok(()), validate_wifi_key(WifiSecurity::Open, ""));
+        assert!(validate_wifi_key(WifiSecurity::Open, "key").is_err());
+
+        assert_eq!(Ok(()), validate_ssid("home"));
+        assert!(validate_ssid("").is_err());
+
+        assert_eq!(Ok(()), validate_email(""));
+        assert_eq!(Ok(()), validate_email("jdoe@example.com"));
+        assert!(validate_email("jdoe@example").is_err());
+        assert!(validate_email("@example.com").is_err());
+        assert!(validate_email("j doe@example.com").is_err());
+    }
+
+    /// test function for the rendering and serialization of entry kinds
+    #[test]
+    fn assert_kinds_are_rendered() {
+        let card = EntryKind::Card(CardDetails {
+            cardholder: "John Doe".into(),
+            number: "4242424242424242".into(),
+            expiry: "09/27".into(),
+        });
+
+        assert_eq!("•••• 4242, expires 09/27", card.summary());
+        assert!(!card.checks_strength());
+        assert!(card.validate_secret("12").is_err());
+
+        let json = serde_json::to_string(&card).unwrap();
+        assert!(json.starts_with("{\"type\":\"card\""));
+        assert_eq!(card, serde_json::from_str(&json).unwrap());
+
+        let open = EntryKind::Wifi(WifiDetails {
+            ssid: "cafe".into(),
+            security: WifiSecurity::Open,
+        });
+        assert!(!open.has_secret());
+        assert_eq!("cafe (Open)", open.summary());
+
+        assert!(EntryKind::Login.validate_secret("").is_err());
+        assert!(EntryKind::SecureNote.validate_secret(" ").is_err());
+        assert_eq!(
+            Ok(()),
+            EntryKind::Identity(IdentityDetails::default()).validate_secret("")
+        );
+        assert!(!EntryKind::Identity(IdentityDetails::default()).requires_secret());
+    }
+}
@@ -0,0 +1,151 @@
+/// Score of each query character matched in a text.
+const MATCH_SCORE: i64 = 16;
+
+/// Bonus of a character matched right after the previous one.
+const CONSECUTIVE_BONUS: i64 = 12;
+
+/// Bonus of a character matched at the start of a word (e.g. "gh" in "Git Hub").
+const WORD_START_BONUS: i64 = 10;
+
+/// Penalty of each character skipped between two matched characters.
+const GAP_PENALTY: i64 = 2;
+
+/// Maximum penalty of a gap between two matched characters.
+const MAX_GAP_PENALTY: i64 = 12;
+
+/// Bonus of a query found as is in a text.
+const SUBSTRING_BONUS: i64 = 24;
+
+/// Bonus of a query found at the start of a text.
+const PREFIX_BONUS: i64 = 16;
+
+/// Bonus of a query equal to a text.
+const EXACT_BONUS: i64 = 32;
+
+/// Scores how well a text matches a query, the higher the better.
+///
+/// Every character of the query must appear in the text, in the same order
+/// (ignoring case and whitespaces). Consecutive characters, characters at the
+/// start of words, and the whole query found as is are rewarded.
+///
+/// Returns `None` if the text does not match the query.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let original: Vec<char> = text.chars().collect();
+    let lowered: Vec<char> = original
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut previous: Option<usize> = None;
+
+    for (index, c) in lowered.iter().enumerate() {
+        if matched == query.len() {
+            break;
+        }
+
+        if *c != query[matched] {
+            continue;
+        }
+
+        score += MATCH_SCORE;
+
+        match previous {
+            Some(previous) if previous + 1 == index => score += CONSECUTIVE_BONUS,
+            Some(previous) => {
+                score -= (GAP_PENALTY * (index - previous - 1) as i64).min(MAX_GAP_PENALTY)
+            }
+            None => {}
+        }
+
+        if is_word_start(&original, index) {
+            score += WORD_START_BONUS;
+        }
+
+        previous = Some(index);
+        matched += 1;
+    }
+
+    if matched < query.len() {
+        return None;
+    }
+
+    let query: String = query.into_iter().collect();
+    let lowered: String = lowered.into_iter().collect();
+
+    if lowered == query {
+        score += EXACT_BONUS;
+    }
+
+    if lowered.starts_with(&query) {
+        score += PREFIX_BONUS;
+    }
+
+    if lowered.contains(&query) {
+        score += SUBSTRING_BONUS;
+    }
+
+    Some(score)
+}
+
+/// Checks either a character starts a word: it follows a separator, or is an uppercase
+/// letter following a lowercase one (e.g. "Hub" in "GitHub").
+fn is_word_start(text: &[char], index: usize) -> bool {
+    let Some(previous) = index.checked_sub(1).map(|index| text[index]) else {
+        return true;
+    };
+
+    !previous.is_alphanumeric() || (previous.is_lowercase() && text[index].is_uppercase())
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the matching of texts
+    #[test]
+    fn assert_texts_are_matched() {
+        assert!(score("gh", "GitHub").is_some());
+        assert!(score("GITHUB", "github").is_some());
+        assert!(score("git hub", "github").is_some());
+        assert!(score("", "anything").is_some());
+
+        assert!(score("hg", "GitHub").is_none());
+        assert!(score("gitlab", "GitHub").is_none());
+    }
+
+    /// test function for the ranking of matched texts
+    #[test]
+    fn assert_matches_are_ranked() {
+        let rank = |query: &str, texts: &[&'static str]| {
+            let mut ranked: Vec<(i64, &str)> = texts
+                .iter()
+                .filter_map(|text| Some((score(query, text)?, *text)))
+                .collect();
+            ranked.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+            ranked.into_iter().map(|(_, text)| text).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec!["mail", "gmail", "my mailbox", "main list"],
+            rank("mail", &["main list", "my mailbox", "gmail", "mail"])
+        );
+        assert_eq!(
+            vec!["GitHub", "gitea hub"],
+            rank("gh", &["gitea hub", "GitHub", "bank"])
+        );
+    }
+}
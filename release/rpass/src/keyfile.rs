@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{crypto, secret::SecretKey};
+
+/// Size of a generated keyfile, in bytes.
+const KEYFILE_LENGTH: usize = 64;
+
+/// Maximum size of a keyfile, in bytes.
+const KEYFILE_MAX_LENGTH: u64 = 1024 * 1024;
+
+/// Size of a keyfile fingerprint, in bytes.
+pub const KEYFILE_FINGERPRINT_LENGTH: usize = 16;
+
+/// Domain separation prefix of keyfile fingerprints.
+const KEYFILE_FINGERPRINT_PREFIX: &[u8] = b"rpass-keyfile-fingerprint";
+
+/// Possible errors upon keyfile operations.
+#[derive(Debug, Error)]
+pub enum KeyfileError {
+    #[error("Keyfile {0} already exists")]
+    AlreadyExists(PathBuf),
+    #[error("Keyfile {0} cannot be read: {1}")]
+    Unreadable(PathBuf, String),
+    #[error("Keyfile {0} is empty")]
+    Empty(PathBuf),
+    #[error("Keyfile {0} is too large (1 MiB max)")]
+    TooLarge(PathBuf),
+}
+
+/// Keyfile used as a second unlock factor, along the master password.
+///
+/// Only the SHA-256 digest of the keyfile content is kept in memory, as a secret.
+#[derive(Debug, Clone)]
+pub struct Keyfile {
+    /// SHA-256 digest of the keyfile content.
+    digest: SecretKey,
+}
+
+impl Keyfile {
+    /// Generates a new random keyfile, only readable by the current user.
+    ///
+    /// This will return an error if :
+    /// - the keyfile already exists.
+    /// - the keyfile cannot be written.
+    pub fn generate(path: &Path) -> Result<Keyfile> {
+        let content = Zeroizing::new(crypto::random_bytes::<KEYFILE_LENGTH>());
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = match options.open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                bail!(KeyfileError::AlreadyExists(path.to_path_buf()))
+            }
+            Err(error) => bail!(error),
+        };
+
+        file.write_all(content.as_ref())?;
+        file.sync_all()?;
+
+        Ok(Self::from_content(content.as_ref()))
+    }
+
+    /// Loads an existing keyfile.
+    ///
+    /// This will return an error if the keyfile cannot be read, is empty or too large.
+    pub fn load(path: &Path) -> Result<Keyfile> {
+        let unreadable =
+            |error: std::io::Error| KeyfileError::Unreadable(path.to_path_buf(), error.to_string());
+
+        let metadata = fs::metadata(path).map_err(unreadable)?;
+
+        if metadata.len() > KEYFILE_MAX_LENGTH {
+            bail!(KeyfileError::TooLarge(path.to_path_buf()));
+        }
+
+        let content = Zeroizing::new(fs::read(path).map_err(unreadable)?);
+
+        if content.is_empty() {
+            bail!(KeyfileError::Empty(path.to_path_buf()));
+        }
+
+        Ok(Self::from_content(&content))
+    }
+
+    /// returns the secret mixed into the key derivation of the master password.
+    pub fn secret(&self) -> &[u8] {
+        self.digest.expose()
+    }
+
+    /// returns the fingerprint of the keyfile, used to tell a wrong keyfile from
+    /// a wrong master password.
+    pub fn fingerprint(&self) -> [u8; KEYFILE_FINGERPRINT_LENGTH] {
+        let mut hasher = Sha256::new();
+        hasher.update(KEYFILE_FINGERPRINT_PREFIX);
+        hasher.update(self.digest.expose());
+
+        let mut fingerprint = [0u8; KEYFILE_FINGERPRINT_LENGTH];
+        fingerprint.copy_from_slice(&hasher.finalize()[..KEYFILE_FINGERPRINT_LENGTH]);
+
+        fingerprint
+    }
+
+    /// creates a keyfile from its content.
+    fn from_content(content: &[u8]) -> Keyfile {
+        let mut digest = Sha256::digest(content);
+        let keyfile = Keyfile {
+            digest: SecretKey::from_slice(&digest).expect("SHA-256 digests are 256 bits long"),
+        };
+        digest.as_mut_slice().zeroize();
+
+        keyfile
+    }
+}
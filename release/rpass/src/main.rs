@@ -2,20 +2,39 @@ use clap::Parser;
 use cli::Cli;
 use console_utils::ConsoleIO;
 
+pub mod agent;
+pub mod attachment;
 pub mod cli;
+pub mod clipboard;
 pub mod console_utils;
 pub mod constants;
 pub mod crypto;
 pub mod data_store;
+pub mod entry;
+pub mod fuzzy;
+pub mod keyfile;
 pub mod middleware;
+pub mod otp;
+pub mod password_source;
 pub mod passwords;
+pub mod secret;
+pub mod shell;
+pub mod storage;
+#[cfg(test)]
+mod test_utils;
+pub mod tui;
 pub mod utils;
+pub mod vault_format;
 
 fn main() {
+    secret::harden_process();
+
     let cli = Cli::parse();
 
     if let Err(error) = middleware::handle(&cli) {
         let console = ConsoleIO::new();
         console.error(&format!("{error}"));
+
+        std::process::exit(1);
     }
 }
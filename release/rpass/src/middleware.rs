@@ -1,15 +1,38 @@
 use crate::{
-    cli::{Cli, Command},
+    agent::{self, AgentClient, AgentError},
+    attachment::{self, Attachment},
+    cli::{BackupsCommand, Cli, Command, CopiedField, KdfCommand},
+    clipboard::Clipboard,
     console_utils::ConsoleIO,
-    data_store::{DataStore, PasswordStore, Unlocked},
+    constants::RPASS_EXPIRY_WARNING_DAYS,
+    crypto,
+    data_store::{CustomField, DataStore, DataStoreError, Dirty, Opened, PasswordStore, Unlocked},
+    entry::{self, CardDetails, EntryError, EntryKind, IdentityDetails, WifiDetails, WifiSecurity},
+    keyfile::Keyfile,
+    otp::OtpSecret,
+    password_source::PasswordSource,
     passwords,
+    secret::SecretString,
+    shell::{self, ShellError},
+    tui, utils,
 };
 use anyhow::{bail, Result};
-use chrono::{DateTime, Local, Utc};
-use inquire::{required, PasswordDisplayMode};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use inquire::{required, validator::Validation, CustomUserError, PasswordDisplayMode};
 use itertools::Itertools;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, IsTerminal},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
 use thiserror::Error;
 
+/// Mask displayed in place of a hidden password.
+pub const PASSWORD_MASK: &str = "********";
+
 /// Possible errors upon handling passwords and datastore.
 #[derive(Debug, Error)]
 pub enum HandlingError {
@@ -21,12 +44,46 @@ pub enum HandlingError {
     DestroyAborted,
     #[error("Password addition aborted")]
     AdditionAborted,
+    #[error("Password edition aborted")]
+    EditAborted,
     #[error("Password deletion aborted")]
     DeleteAborted,
+    #[error("Password restoration aborted")]
+    RestoreAborted,
+    #[error("Backup restoration aborted")]
+    BackupRestoreAborted,
+    #[error("Master password change aborted")]
+    MasterPasswordChangeAborted,
+    #[error("New master password must be different from the current one")]
+    SameMasterPassword,
     #[error("Key \"{0}\" already exists in datastore")]
     KeyAlreadyExists(String),
     #[error("Key \"{0}\" is not in datastore")]
     KeyNotFound(String),
+    #[error("A password name is required")]
+    NameRequired,
+    #[error("Datastore has no password yet")]
+    EmptyDataStore,
+    #[error("Passwords can only be revealed in a terminal, use rpass dump in command chains")]
+    RevealOutsideTerminal,
+    #[error("Password \"{0}\" has no {1} to copy")]
+    EmptyField(String, &'static str),
+    #[error("Password \"{0}\" has no OTP secret, import one with rpass otp --set")]
+    OtpNotSet(String),
+    #[error("OTP secret removal aborted")]
+    OtpRemoveAborted,
+    #[error("Password \"{0}\" has no field \"{1}\"")]
+    FieldNotFound(String, String),
+    #[error("Password \"{0}\" already has an attachment named \"{1}\"")]
+    AttachmentAlreadyExists(String, String),
+    #[error("Password \"{0}\" has no attachment named \"{1}\"")]
+    AttachmentNotFound(String, String),
+    #[error("Attachment removal aborted")]
+    AttachmentRemoveAborted,
+    #[error("{0} is not a file path")]
+    InvalidAttachmentPath(PathBuf),
+    #[error("{0} password(s) expired or expiring within {1} day(s)")]
+    PasswordsExpiring(usize, i64),
 }
 
 /// Entrypoint of the middleware.
@@ -35,99 +92,332 @@ pub enum HandlingError {
 ///
 /// This will transmit any error in middleware to the `main()` function.
 pub fn handle(cli: &Cli) -> Result<()> {
-    let master_password = if cli.master_password.is_none() {
-        require_master_password()?
-    } else {
-        cli.master_password.as_ref().unwrap().to_owned()
-    };
+    // the clipboard is cleared in the background, without any DataStore
+    if let Command::ClearClipboard { after } = cli.command {
+        return clear_clipboard(cli, after);
+    }
+
+    let data_store = DataStore::new(utils::resolve_store_file_path(
+        cli.store.as_deref(),
+        cli.vault.as_deref(),
+    )?);
 
-    let data_store = DataStore::new();
+    let is_init = matches!(cli.command.clone(), Command::Init);
 
-    if !data_store.is_initialized()? && !matches!(cli.command.clone(), Command::Init) {
+    if !data_store.is_initialized()? && !is_init {
         bail!(HandlingError::NotInitialized);
     }
 
-    let unlocked = match cli.command.clone() {
-        Command::List => list(data_store, &master_password)?,
-        Command::Init => init(data_store, &master_password)?,
-        Command::Add => add(data_store, &master_password)?,
-        Command::Delete { name } => delete(data_store, &name, &master_password)?,
-        Command::Dump { name } => dump(data_store, &name, &master_password)?,
-        Command::Generate => generate(data_store, &master_password)?,
-    };
+    let data_store = data_store.with_keyfile(get_keyfile(cli, is_init)?);
+
+    let agent = AgentClient::connect(data_store.path());
+
+    // these commands are operated on the DataStore files or on the agent, without unlocking the DataStore
+    match cli.command.clone() {
+        Command::Backups { command } => {
+            return match command {
+                BackupsCommand::List => list_backups(&data_store),
+                BackupsCommand::Restore { version } => {
+                    require_no_agent(&agent)?;
+                    restore_backup(&data_store, version, cli)
+                }
+            };
+        }
+        Command::Agent {
+            timeout,
+            foreground,
+        } => return start_agent(data_store, agent, timeout, foreground, cli),
+        Command::Lock => return lock_agent(agent),
+        _ => {}
+    }
+
+    // only the commands modifying the DataStore write it back to the filesystem
+    match cli.command.clone() {
+        Command::Init => init(data_store, get_master_password(cli)?.expose())?.lock()?,
+        Command::List => read(open(data_store, agent, cli)?, list)?,
+        Command::Add => write(open(data_store, agent, cli)?, add)?,
+        Command::Edit { name } => write(open(data_store, agent, cli)?, |modified| {
+            edit(modified, &name)
+        })?,
+        Command::Delete { name } => write(open(data_store, agent, cli)?, |modified| {
+            delete(modified, name.as_deref())
+        })?,
+        Command::Dump { name, field } => read(open(data_store, agent, cli)?, |opened| {
+            dump(opened, name.as_deref(), field.as_deref())
+        })?,
+        Command::Attach { name, file } => write(open(data_store, agent, cli)?, |modified| {
+            attach(modified, &name, &file)
+        })?,
+        Command::Detach {
+            name,
+            attachment,
+            output,
+            remove,
+        } => {
+            let mut modified = open(data_store, agent, cli)?.modify();
+            let changed = detach(&mut modified, &name, &attachment, output.as_deref(), remove)?;
+
+            end(modified, changed)?
+        }
+        Command::Show {
+            name,
+            reveal,
+            timeout,
+        } => read(open(data_store, agent, cli)?, |opened| {
+            show(opened, name.as_deref(), reveal.then_some(timeout))
+        })?,
+        Command::Copy {
+            name,
+            field,
+            timeout,
+        } => {
+            let clipboard = get_clipboard(cli)?;
+
+            read(open(data_store, agent, cli)?, |opened| {
+                copy(opened, name.as_deref(), field, timeout, &clipboard)
+            })?
+        }
+        Command::Otp {
+            name, set: true, ..
+        } => write(open(data_store, agent, cli)?, |modified| {
+            set_otp(modified, name.as_deref())
+        })?,
+        Command::Otp {
+            name, remove: true, ..
+        } => write(open(data_store, agent, cli)?, |modified| {
+            remove_otp(modified, name.as_deref())
+        })?,
+        Command::Otp { name, .. } => {
+            let mut modified = open(data_store, agent, cli)?.modify();
+            otp(&mut modified, name.as_deref())?;
 
-    unlocked.lock()?;
+            end(modified, false)?
+        }
+        Command::Search { query } => read(open(data_store, agent, cli)?, |opened| {
+            search(opened, &query)
+        })?,
+        Command::Expiring { within } => read(open(data_store, agent, cli)?, |opened| {
+            expiring(opened, within)
+        })?,
+        Command::Generate => read(open(data_store, agent, cli)?, generate)?,
+        Command::History { name, reveal } => read(open(data_store, agent, cli)?, |opened| {
+            history(opened, &name, reveal)
+        })?,
+        Command::Restore { name, version } => write(open(data_store, agent, cli)?, |modified| {
+            restore(modified, &name, version)
+        })?,
+        Command::Tui => {
+            let mut modified = open(data_store, agent, cli)?.modify();
+            let changed = tui::browse(&mut modified)?;
+
+            end(modified, changed)?
+        }
+        Command::Shell { timeout } => shell::run(
+            open(data_store, agent, cli)?.modify(),
+            Duration::from_secs(timeout),
+            |modified, command| run_in_shell(modified, command, cli),
+        )?,
+        Command::ChangeMaster => {
+            require_no_agent(&agent)?;
+            change_master(data_store, get_master_password(cli)?.expose())?
+        }
+        Command::Kdf {
+            command: KdfCommand::Calibrate { target_ms },
+        } => {
+            require_no_agent(&agent)?;
+            calibrate_kdf(data_store, target_ms, get_master_password(cli)?.expose())?.lock()?
+        }
+        Command::Backups { .. }
+        | Command::Agent { .. }
+        | Command::Lock
+        | Command::ClearClipboard { .. } => {
+            unreachable!("handled beforehand")
+        }
+    };
 
     Ok(())
 }
 
-/// Initializes the DataStore on the system.
+/// Runs a handler only reading the DataStore, then closes it.
+///
+/// The DataStore is not written back to the filesystem, unless it has been
+/// loaded from an older vault file format and needs to be migrated.
+///
+/// This will return an error if :
+/// - the handler fails.
+/// - the migrated DataStore cannot be saved.
+fn read(
+    opened: DataStore<Unlocked>,
+    handler: impl FnOnce(&DataStore<Unlocked>) -> Result<()>,
+) -> Result<DataStore> {
+    handler(&opened)?;
+
+    if opened.is_outdated() {
+        return opened.modify().lock();
+    }
+
+    Ok(opened.close())
+}
+
+/// Runs a handler modifying the DataStore, then locks and saves it.
+///
+/// Nothing is written to the filesystem when the handler fails.
+///
+/// This will return an error if :
+/// - the handler fails.
+/// - the DataStore cannot be saved.
+fn write(
+    opened: DataStore<Unlocked>,
+    handler: impl FnOnce(&mut DataStore<Dirty>) -> Result<()>,
+) -> Result<DataStore> {
+    let mut modified = opened.modify();
+
+    handler(&mut modified)?;
+
+    modified.lock()
+}
+
+/// Ends the modification of the DataStore, which is only saved if it has actually
+/// been modified (or needs to be migrated), and locks it.
+///
+/// This will return an error if the DataStore cannot be saved.
+fn end(modified: DataStore<Dirty>, changed: bool) -> Result<DataStore> {
+    if changed || modified.is_outdated() {
+        return modified.lock();
+    }
+
+    Ok(modified.close())
+}
+
+/// Runs a command of the interactive shell against the already unlocked DataStore.
+///
+/// Returns either the DataStore has been modified by the command.
 ///
 /// This will return an error if :
-/// - the initialization fails.
-/// - the datastore cannot be unlocked.
-fn init(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlocked>> {
+/// - the command fails.
+/// - the command is not available in the shell.
+fn run_in_shell(modified: &mut DataStore<Dirty>, command: Command, cli: &Cli) -> Result<bool> {
+    match command {
+        Command::List => list(modified).map(|_| false),
+        Command::Add => add(modified).map(|_| true),
+        Command::Edit { name } => edit(modified, &name).map(|_| true),
+        Command::Delete { name } => delete(modified, name.as_deref()).map(|_| true),
+        Command::Dump { name, field } => {
+            dump(modified, name.as_deref(), field.as_deref()).map(|_| false)
+        }
+        Command::Attach { name, file } => attach(modified, &name, &file).map(|_| true),
+        Command::Detach {
+            name,
+            attachment,
+            output,
+            remove,
+        } => detach(modified, &name, &attachment, output.as_deref(), remove),
+        Command::Show {
+            name,
+            reveal,
+            timeout,
+        } => show(modified, name.as_deref(), reveal.then_some(timeout)).map(|_| false),
+        Command::Copy {
+            name,
+            field,
+            timeout,
+        } => copy(
+            modified,
+            name.as_deref(),
+            field,
+            timeout,
+            &get_clipboard(cli)?,
+        )
+        .map(|_| false),
+        Command::Otp {
+            name, set: true, ..
+        } => set_otp(modified, name.as_deref()).map(|_| true),
+        Command::Otp {
+            name, remove: true, ..
+        } => remove_otp(modified, name.as_deref()).map(|_| true),
+        Command::Otp { name, .. } => otp(modified, name.as_deref()).map(|_| false),
+        Command::Search { query } => search(modified, &query).map(|_| false),
+        Command::Expiring { within } => expiring(modified, within).map(|_| false),
+        Command::Generate => generate(modified).map(|_| false),
+        Command::History { name, reveal } => history(modified, &name, reveal).map(|_| false),
+        Command::Restore { name, version } => restore(modified, &name, version).map(|_| true),
+        Command::Tui => tui::browse(modified),
+        _ => bail!(ShellError::UnsupportedCommand),
+    }
+}
+
+/// Initializes the DataStore on the system.
+///
+/// This will return an error if the initialization fails.
+fn init(data_store: DataStore, master_password: &str) -> Result<DataStore<Dirty>> {
     if data_store.is_initialized()? {
         bail!(HandlingError::AlreadyInitialized);
     }
 
-    data_store.initialize(master_password)?;
-    let opened = data_store.unlock(master_password)?;
+    let opened = with_spinner("Initializing datastore...", || {
+        data_store.initialize(master_password)
+    })?;
 
     let console = ConsoleIO::new();
 
-    console.success("Datastore initialized !");
+    console.success(&format!(
+        "Datastore initialized in {} !",
+        opened.path().display()
+    ));
 
     Ok(opened)
 }
 
-/// Adds a password to the DataStore.
+/// Adds a password (or any other kind of entry) to the DataStore.
+///
+/// The fields asked depend on the kind of entry picked: logins have an URL and a
+/// login, and the other kinds have their own fields (e.g. the number of a card).
 ///
 /// This will return an error if :
-/// - the datastore cannot be unlocked.
-/// - the password label cannot be read.
+/// - the kind of entry or the password label cannot be read.
 /// - the password label already exists in the DataStore.
-/// - the password cannot be read.
+/// - the fields of the entry cannot be read.
+/// - the password (or the secret of the entry) cannot be read.
 /// - the password strength cannot be calculated.
 /// - the "unsafe password addition" is not confirmed.
 /// - the password data cannot be recorded in the DataStore.
-fn add(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlocked>> {
+fn add(modified: &mut DataStore<Dirty>) -> Result<()> {
     let console = ConsoleIO::new();
 
-    let mut opened = data_store.unlock(master_password)?;
+    let kinds = EntryKind::all();
+    let picked = console
+        .input_select(
+            "Kind of entry:",
+            kinds.iter().map(EntryKind::name).collect(),
+        )
+        .raw_prompt()?;
 
     let label = console
-        .input_text("Label/name for this password:")
+        .input_text("Label/name for this entry:")
         .with_validator(required!())
         .prompt()?;
 
-    if opened.get(&label).is_ok() {
+    if modified.get(&label).is_ok() {
         bail!(HandlingError::KeyAlreadyExists(label));
     }
 
-    let url = console.ask_question_default("URL for this password:", "");
-    let login = console.ask_question_default("Login for this password:", "");
+    let kind = ask_kind_details(&console, &kinds[picked.index])?;
 
-    let password = console
-        .input_password("Password:")
-        .with_display_mode(PasswordDisplayMode::Masked)
-        .with_validator(required!())
-        .prompt()?;
+    let (url, login) = match kind {
+        EntryKind::Login => (
+            console.ask_question_default("URL for this password:", ""),
+            console.ask_question_default("Login for this password:", ""),
+        ),
+        _ => (String::new(), String::new()),
+    };
 
-    let password_strength_label = passwords::format_password_strength(&password)?;
-    console.writeln(&format!("Password strength: {password_strength_label}"));
+    let password = require_new_secret(&console, &kind, HandlingError::AdditionAborted)?;
 
-    if passwords::get_password_strength(&password)? < 3 {
-        let confirmed = console.ask_confirm(
-            "Your password seems to be not safe enough, are you sure you want to store it as it is",
-        );
+    let comment = console.ask_question_default("Comment for this password:", "");
 
-        if !confirmed {
-            bail!(HandlingError::AdditionAborted);
-        }
-    }
+    let fields = ask_custom_fields(&console, vec![])?;
 
-    let comment = console.ask_question_default("Comment for this password:", "");
+    let expiration_date = ask_expiration_date(&console, None)?;
 
     let data = PasswordStore {
         label: label.clone(),
@@ -140,30 +430,129 @@ fn add(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlocke
             Some(comment)
         },
         creation_date: Utc::now(),
+        modification_date: None,
+        history: vec![],
+        otp: None,
+        expiration_date,
+        kind,
+        fields,
+        attachments: vec![],
     };
 
-    opened.insert(&data)?;
+    modified.insert(&data)?;
 
     console.success(&format!("Password \"{label}\" added !"));
 
-    Ok(opened)
+    Ok(())
 }
 
-/// Lists all the stored password in the DataStore.
+/// Edits an existing password of the DataStore given its label.
 ///
-/// This will return an error if the DataStore cannot be unlocked.
-fn list(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlocked>> {
-    let opened = data_store.unlock(master_password)?;
+/// Every prompt is prefilled with the current value of the entry, and the
+/// password is only asked (and checked) again if the user wants to change it,
+/// or if it does not fit the edited fields anymore (e.g. the security of a Wi-Fi network).
+///
+/// This will return an error if :
+/// - the password label is not found in the DataStore.
+/// - the new values cannot be read.
+/// - the password strength cannot be calculated.
+/// - the "unsafe password edition" is not confirmed.
+/// - the password data cannot be updated in the DataStore.
+fn edit(modified: &mut DataStore<Dirty>, label: &str) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let current = match modified.get(label) {
+        Ok(data) => data.clone(),
+        Err(_) => bail!(HandlingError::KeyNotFound(label.into())),
+    };
+
+    let kind = ask_kind_details(&console, &current.kind)?;
+
+    let (url, login) = match kind {
+        EntryKind::Login => (
+            console
+                .input_text("URL for this password:")
+                .with_initial_value(&sanitize_none_option_string(current.url.clone()))
+                .prompt()?,
+            console
+                .input_text("Login for this password:")
+                .with_initial_value(&sanitize_none_option_string(current.login.clone()))
+                .prompt()?,
+        ),
+        _ => (
+            sanitize_none_option_string(current.url.clone()),
+            sanitize_none_option_string(current.login.clone()),
+        ),
+    };
+
+    let secret_name = kind.secret_name().to_lowercase();
+
+    let password = if !kind.has_secret() {
+        SecretString::default()
+    } else if kind.validate_secret(current.password.expose()).is_err() {
+        console.warning(&format!(
+            "The current {secret_name} does not fit this entry anymore, please enter a new one"
+        ));
+        require_new_secret(&console, &kind, HandlingError::EditAborted)?
+    } else if console
+        .input_confirm(&format!("Do you want to change the {secret_name}"))
+        .with_default(false)
+        .prompt()?
+    {
+        require_new_secret(&console, &kind, HandlingError::EditAborted)?
+    } else {
+        current.password.clone()
+    };
+
+    let comment = console
+        .input_text("Comment for this password:")
+        .with_initial_value(&sanitize_none_option_string(current.comment.clone()))
+        .prompt()?;
+
+    let fields = ask_custom_fields(&console, current.fields.clone())?;
+
+    let expiration_date = ask_expiration_date(&console, current.expiration_date)?;
+
+    let data = PasswordStore {
+        login: if login.is_empty() { None } else { Some(login) },
+        password,
+        url: if url.is_empty() { None } else { Some(url) },
+        comment: if comment.is_empty() {
+            None
+        } else {
+            Some(comment)
+        },
+        modification_date: Some(Utc::now()),
+        expiration_date,
+        kind,
+        fields,
+        ..current
+    };
+
+    modified.update(&data)?;
+
+    console.success(&format!("Password \"{label}\" updated !"));
+
+    Ok(())
+}
 
+/// Lists all the stored password in the DataStore.
+///
+/// This will return an error if the password strength cannot be calculated.
+fn list<State: Opened>(opened: &DataStore<State>) -> Result<()> {
     let headers: Vec<String> = vec![
         "Label".to_string(),
+        "Kind".to_string(),
         "Url".to_string(),
         "Login".to_string(),
         "Comment".to_string(),
         "Creation date".to_string(),
+        "Modification date".to_string(),
+        "Expiration date".to_string(),
         "Password strength".to_string(),
     ];
 
+    let today = Local::now().date_naive();
     let mut lines: Vec<Vec<String>> = vec![];
 
     for (_, data) in opened.data().iter().sorted_by_key(|x| x.0) {
@@ -172,14 +561,26 @@ fn list(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlock
         let comment = sanitize_none_option_string(data.comment.clone());
 
         let local_time: DateTime<Local> = DateTime::from(data.creation_date);
+        let modification_time = match data.modification_date {
+            Some(date) => DateTime::<Local>::from(date).format("%v %X").to_string(),
+            None => "".into(),
+        };
+
+        let password_strength = match data.kind.checks_strength() {
+            true => passwords::format_password_strength(data.password.expose())?,
+            false => "".into(),
+        };
 
         lines.push(vec![
             data.label.clone(),
+            format_kind(&data.kind),
             url,
             login,
             comment,
             local_time.format("%v %X").to_string(),
-            passwords::format_password_strength(&data.password)?,
+            modification_time,
+            format_expiration_date(data, today, RPASS_EXPIRY_WARNING_DAYS),
+            password_strength,
         ]);
     }
 
@@ -187,98 +588,1488 @@ fn list(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlock
 
     console.string_table(headers, lines);
 
-    Ok(opened)
+    Ok(())
+}
+
+/// Shows all the fields of a password given its label, the password being masked.
+///
+/// The fields shown depend on the kind of entry (e.g. a card shows its cardholder,
+/// its masked number and its expiry, and its security code in place of the password).
+///
+/// The password is picked among the matching ones if the label is missing or not exact.
+/// With `reveal_secs`, the password is then displayed for this number of seconds (or
+/// until a key is pressed), and cleared from the terminal.
+///
+/// This will return an error if :
+/// - the password is to be revealed outside of a terminal.
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the password cannot be revealed.
+fn show<State: Opened>(
+    opened: &DataStore<State>,
+    name: Option<&str>,
+    reveal_secs: Option<u64>,
+) -> Result<()> {
+    if reveal_secs.is_some() && !io::stdout().is_terminal() {
+        bail!(HandlingError::RevealOutsideTerminal);
+    }
+
+    let console = ConsoleIO::new();
+
+    let label = pick_label(opened, name)?;
+    let data = opened.get(&label)?;
+
+    let secret_name = data.kind.secret_name();
+
+    let mut fields = vec![("Kind", data.kind.to_string())];
+    fields.extend(data.kind.details());
+
+    if data.kind == EntryKind::Login {
+        fields.extend([
+            ("Url", sanitize_none_option_string(data.url.clone())),
+            ("Login", sanitize_none_option_string(data.login.clone())),
+        ]);
+    }
+
+    if data.kind.has_secret() {
+        let masked = match data.password.expose().is_empty() {
+            true => "".into(),
+            false => PASSWORD_MASK.to_string(),
+        };
+
+        fields.push((secret_name, masked));
+    }
+
+    fields.push(("Comment", sanitize_none_option_string(data.comment.clone())));
+
+    for field in &data.fields {
+        let value = match field.secret {
+            true => PASSWORD_MASK.to_string(),
+            false => field.value.expose().to_string(),
+        };
+
+        fields.push((&field.name, value));
+    }
+
+    console.title(&data.label);
+    console.key_value_pair(fields);
+
+    let local_time: DateTime<Local> = DateTime::from(data.creation_date);
+    let modification_time = match data.modification_date {
+        Some(date) => DateTime::<Local>::from(date).format("%v %X").to_string(),
+        None => "".into(),
+    };
+
+    let mut details = BTreeMap::from([
+        (
+            "Creation date".to_string(),
+            local_time.format("%v %X").to_string(),
+        ),
+        ("Modification date".to_string(), modification_time),
+        (
+            "Expiration date".to_string(),
+            format_expiration_date(data, Local::now().date_naive(), RPASS_EXPIRY_WARNING_DAYS),
+        ),
+        (
+            "Previous passwords".to_string(),
+            data.history.len().to_string(),
+        ),
+        (
+            "Two-factor".to_string(),
+            data.otp
+                .as_ref()
+                .map_or("None".to_string(), |secret| secret.to_string()),
+        ),
+    ]);
+
+    if data.kind.checks_strength() {
+        details.insert(
+            "Password strength".to_string(),
+            passwords::format_password_strength(data.password.expose())?,
+        );
+    }
+
+    console.section("Details");
+    console.definition_list(details);
+
+    if !data.attachments.is_empty() {
+        console.section("Attachments");
+        console.definition_list(
+            data.attachments
+                .iter()
+                .map(|attachment| {
+                    let attachment_time: DateTime<Local> =
+                        DateTime::from(attachment.attachment_date);
+
+                    (
+                        attachment.name.clone(),
+                        format!(
+                            "{}, attached on {}",
+                            attachment::format_size(attachment.size),
+                            attachment_time.format("%v %X")
+                        ),
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    if let Some(seconds) = reveal_secs {
+        console.reveal(
+            secret_name,
+            data.password.expose(),
+            Duration::from_secs(seconds),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Searches passwords by fuzzy matching their label, login, URL, comment and the
+/// searchable fields of their kind (e.g. the SSID of a Wi-Fi network).
+///
+/// The best matches are listed first.
+fn search<State: Opened>(opened: &DataStore<State>, query: &str) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let matches = opened.search(query);
+
+    if matches.is_empty() {
+        console.info(&format!("No password matches \"{query}\""));
+        return Ok(());
+    }
+
+    let headers: Vec<String> = vec![
+        "Label".to_string(),
+        "Kind".to_string(),
+        "Url".to_string(),
+        "Login".to_string(),
+        "Comment".to_string(),
+    ];
+
+    let lines: Vec<Vec<String>> = matches
+        .iter()
+        .map(|data| {
+            vec![
+                data.label.clone(),
+                format_kind(&data.kind),
+                sanitize_none_option_string(data.url.clone()),
+                sanitize_none_option_string(data.login.clone()),
+                sanitize_none_option_string(data.comment.clone()),
+            ]
+        })
+        .collect();
+
+    console.string_table(headers, lines);
+
+    Ok(())
 }
 
 /// Deletes password data from the DataStore given a password label.
 ///
+/// The password is picked among the matching ones if the label is missing or not exact.
+/// The blobs of its attachments are deleted along, once the DataStore is saved.
+///
 /// This will return an error if :
-/// - the datastore cannot be unlocked.
-/// - the label of the password to delete is not found in the DataStore.
+/// - the password to delete cannot be found (or picked) in the DataStore.
 /// - the deletion is not confirmed.
 /// - the actual data deletion cannot be made.
-fn delete(
-    data_store: DataStore,
-    label: &str,
-    master_password: &str,
-) -> Result<DataStore<Unlocked>> {
+fn delete(modified: &mut DataStore<Dirty>, name: Option<&str>) -> Result<()> {
     let console = ConsoleIO::new();
 
-    let mut opened = data_store.unlock(master_password)?;
+    let label = pick_label(modified, name)?;
 
-    if opened.get(label).is_err() {
-        bail!(HandlingError::KeyNotFound(label.into()));
-    }
+    let question = match modified.get(&label)?.attachments.len() {
+        0 => format!("Are you sure you want to delete entry \"{label}\""),
+        count => format!(
+            "Are you sure you want to delete entry \"{label}\" and its {count} attached file(s)"
+        ),
+    };
 
-    let confirmed = console.ask_confirm(&format!(
-        "Are you sure you want to delete entry \"{label}\""
-    ));
+    let confirmed = console.ask_confirm(&question);
 
     if !confirmed {
         bail!(HandlingError::DeleteAborted);
     }
 
-    opened.delete(label)?;
+    modified.delete(&label)?;
 
     console.success(&format!("Entry \"{label}\" deleted !"));
 
-    Ok(opened)
+    Ok(())
 }
 
-/// Prints a password to the standard output given its label.
+/// Prints a password (or one of its custom fields) to the standard output given its label.
+///
+/// The password is picked among the matching ones if the label is missing or not exact.
 ///
 /// ⚠️ This should only be used in command chains. ⚠️
 ///
 /// This will return an error if :
-/// - the DataStore cannot be unlocked.
-/// - the password label is not found in the DataStore.
-fn dump(data_store: DataStore, label: &str, master_password: &str) -> Result<DataStore<Unlocked>> {
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the password has no custom field of the given name.
+fn dump<State: Opened>(
+    opened: &DataStore<State>,
+    name: Option<&str>,
+    field: Option<&str>,
+) -> Result<()> {
     let console = ConsoleIO::new();
 
-    let opened = data_store.unlock(master_password)?;
+    let label = pick_label(opened, name)?;
+    let data = opened.get(&label)?;
 
-    if let Ok(data) = opened.get(label) {
-        console.write(&data.password);
-    } else {
-        bail!(HandlingError::KeyNotFound(label.into()));
-    }
+    let value = match field {
+        Some(field) => match data.field(field) {
+            Some(field) => field.value.expose(),
+            None => bail!(HandlingError::FieldNotFound(label, field.into())),
+        },
+        None => data.password.expose(),
+    };
 
-    Ok(opened)
+    console.write(value);
+
+    Ok(())
 }
 
-/// Generates a strong random password of 24 chars.
+/// Attaches a file to a password given its label.
+///
+/// The file is encrypted by chunks into a new blob of the blobs folder next to the
+/// DataStore, and the password data holds the key and the digest of the blob.
+/// The blob is deleted if the DataStore is not saved.
 ///
 /// This will return an error if :
-/// - the DataStore cannot be unlocked.
-/// - the password cannot be generated.
-fn generate(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlocked>> {
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the password already has an attachment of the same name.
+/// - the file cannot be read, or its blob cannot be written.
+/// - the password data cannot be updated in the DataStore.
+fn attach(modified: &mut DataStore<Dirty>, name: &str, file: &Path) -> Result<()> {
     let console = ConsoleIO::new();
 
-    let opened = data_store.unlock(master_password)?;
+    let label = pick_label(modified, Some(name))?;
+    let mut data = modified.get(&label)?.clone();
 
-    let generated = passwords::generate(24)?;
+    let Some(file_name) = file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+    else {
+        bail!(HandlingError::InvalidAttachmentPath(file.to_path_buf()));
+    };
 
-    console.success(&format!("Password generated: {generated}"));
+    if data
+        .attachments
+        .iter()
+        .any(|attachment| attachment.name == file_name)
+    {
+        bail!(HandlingError::AttachmentAlreadyExists(label, file_name));
+    }
 
-    Ok(opened)
+    let blobs_folder = attachment::blobs_folder(modified.path())?;
+    let attachment = with_spinner("Encrypting file...", || {
+        Attachment::create(&blobs_folder, &file_name, file)
+    })?;
+
+    let size = attachment::format_size(attachment.size);
+    let blob = blobs_folder.join(&attachment.blob);
+
+    data.attachments.push(attachment);
+    data.modification_date = Some(Utc::now());
+
+    // once recorded, the blob is deleted along the changes if they are never saved
+    if let Err(error) = modified.update(&data) {
+        let _ = fs::remove_file(blob);
+        bail!(error);
+    }
+
+    console.success(&format!(
+        "File \"{file_name}\" ({size}) attached to \"{label}\" !"
+    ));
+
+    Ok(())
 }
 
-///////////////////// UTILITY FUNCTIONS /////////////////////
+/// Extracts a file attached to a password given its label, into a new file, and/or
+/// removes it from the password.
+///
+/// The blob of a removed file is deleted once the DataStore is saved.
+/// Returns whether the password was modified.
+///
+/// This will return an error if :
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the password has no attachment of the given name.
+/// - the output file already exists.
+/// - the blob of the attachment is missing, corrupted or tampered with.
+/// - the removal is not confirmed.
+fn detach(
+    modified: &mut DataStore<Dirty>,
+    name: &str,
+    attachment_name: &str,
+    output: Option<&Path>,
+    remove: bool,
+) -> Result<bool> {
+    let console = ConsoleIO::new();
 
-/// Requests the master password to unlock a DataStore.
+    let label = pick_label(modified, Some(name))?;
+    let mut data = modified.get(&label)?.clone();
+
+    let Some(attachment) = data
+        .attachments
+        .iter()
+        .find(|attachment| attachment.name == attachment_name)
+    else {
+        bail!(HandlingError::AttachmentNotFound(
+            label,
+            attachment_name.into()
+        ));
+    };
+
+    if let Some(output) = output {
+        let blobs_folder = attachment::blobs_folder(modified.path())?;
+        with_spinner("Decrypting file...", || {
+            attachment.extract(&blobs_folder, output)
+        })?;
+
+        console.success(&format!(
+            "File \"{attachment_name}\" of \"{label}\" extracted to {} !",
+            output.display()
+        ));
+    }
+
+    if !remove {
+        return Ok(false);
+    }
+
+    let confirmed = console.ask_confirm(&format!(
+        "Are you sure you want to remove the file \"{attachment_name}\" of \"{label}\""
+    ));
+
+    if !confirmed {
+        bail!(HandlingError::AttachmentRemoveAborted);
+    }
+
+    data.attachments
+        .retain(|attachment| attachment.name != attachment_name);
+    data.modification_date = Some(Utc::now());
+
+    modified.update(&data)?;
+
+    console.success(&format!(
+        "File \"{attachment_name}\" removed from \"{label}\" !"
+    ));
+
+    Ok(true)
+}
+
+/// Copies a field of a password to the clipboard given its label, instead of
+/// printing it in the terminal.
 ///
-/// This will return an error if the password cannot be read.
-fn require_master_password() -> Result<String> {
-    let console_utils = ConsoleIO::new();
+/// The password is picked among the matching ones if the label is missing or not exact.
+/// The clipboard is cleared after `timeout_secs` by a background process, if it still
+/// holds the copied value.
+///
+/// This will return an error if :
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the field is empty.
+/// - the clipboard cannot be written.
+/// - the clipboard clearing cannot be scheduled.
+fn copy<State: Opened>(
+    opened: &DataStore<State>,
+    name: Option<&str>,
+    field: CopiedField,
+    timeout_secs: u64,
+    clipboard: &Clipboard,
+) -> Result<()> {
+    let console = ConsoleIO::new();
 
-    let password_question = console_utils.input_password("Enter master password:");
+    let label = pick_label(opened, name)?;
+    let entry = opened.get(&label)?;
 
-    let master_password = password_question
-        .without_confirmation()
-        .with_display_mode(PasswordDisplayMode::Masked)
-        .prompt()?;
+    let (field_name, value) = match field {
+        CopiedField::Login => ("login", entry.login.as_deref()),
+        CopiedField::Password => ("password", Some(entry.password.expose())),
+        CopiedField::Url => ("URL", entry.url.as_deref()),
+    };
+
+    let Some(value) = value.filter(|value| !value.is_empty()) else {
+        bail!(HandlingError::EmptyField(label, field_name));
+    };
+
+    clipboard.copy(value.as_bytes())?;
+
+    if !clipboard.can_paste() {
+        console.success(&format!(
+            "Copied {field_name} of \"{label}\" to the clipboard !"
+        ));
+        console.warning("The clipboard cannot be read, so it will not be cleared (see --clipboard-paste-command)");
+
+        return Ok(());
+    }
+
+    clipboard.clear_later(value.as_bytes(), timeout_secs)?;
+
+    console.success(&format!(
+        "Copied {field_name} of \"{label}\" to the clipboard, cleared in {timeout_secs}s !"
+    ));
 
-    Ok(master_password)
+    Ok(())
+}
+
+/// Prints the current one-time password of a password given its label, along its
+/// remaining validity for a time-based one.
+///
+/// The password is picked among the matching ones if the label is missing or not exact.
+/// The counter of a counter-based one-time password is incremented, and the DataStore
+/// is saved before the code is printed.
+///
+/// This will return an error if :
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the password has no OTP secret.
+/// - the code cannot be generated.
+/// - the incremented counter cannot be saved.
+fn otp(modified: &mut DataStore<Dirty>, name: Option<&str>) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let label = pick_label(modified, name)?;
+    let mut data = modified.get(&label)?.clone();
+
+    let Some(secret) = data.otp.as_mut() else {
+        bail!(HandlingError::OtpNotSet(label));
+    };
+
+    let timestamp = Utc::now().timestamp().max(0) as u64;
+    let code = secret.generate(timestamp)?;
+
+    // the incremented counter is saved before the code is shown, so that a
+    // counter-based code is never generated twice
+    if code.remaining_secs.is_none() {
+        modified.update(&data)?;
+        modified.flush()?;
+    }
+
+    console.writeln(&code.code);
+
+    // only the code is printed in command chains
+    if let Some(remaining_secs) = code.remaining_secs.filter(|_| io::stdout().is_terminal()) {
+        console.comment(&format!("valid for {remaining_secs}s"));
+    }
+
+    Ok(())
+}
+
+/// Imports the OTP secret of a password given its label, from an otpauth:// URI
+/// or a base32 string, replacing the current one if any.
+///
+/// The password is picked among the matching ones if the label is missing or not exact.
+///
+/// This will return an error if :
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the secret cannot be read, or is invalid.
+/// - the password data cannot be updated in the DataStore.
+fn set_otp(modified: &mut DataStore<Dirty>, name: Option<&str>) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let label = pick_label(modified, name)?;
+    let mut data = modified.get(&label)?.clone();
+
+    let input = console
+        .input_password("OTP secret (otpauth:// URI or base32):")
+        .without_confirmation()
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .with_validator(|input: &str| {
+            Ok(match OtpSecret::parse(input) {
+                Ok(_) => Validation::Valid,
+                Err(error) => Validation::Invalid(error.to_string().into()),
+            })
+        })
+        .prompt()?;
+
+    let secret = OtpSecret::parse(SecretString::from(input).expose())?;
+
+    let description = secret.to_string();
+
+    data.otp = Some(secret);
+    data.modification_date = Some(Utc::now());
+
+    modified.update(&data)?;
+
+    console.success(&format!(
+        "OTP secret of \"{label}\" imported: {description}"
+    ));
+
+    Ok(())
+}
+
+/// Removes the OTP secret of a password given its label.
+///
+/// The password is picked among the matching ones if the label is missing or not exact.
+///
+/// This will return an error if :
+/// - the password cannot be found (or picked) in the DataStore.
+/// - the password has no OTP secret.
+/// - the removal is not confirmed.
+fn remove_otp(modified: &mut DataStore<Dirty>, name: Option<&str>) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let label = pick_label(modified, name)?;
+    let mut data = modified.get(&label)?.clone();
+
+    if data.otp.is_none() {
+        bail!(HandlingError::OtpNotSet(label));
+    }
+
+    let confirmed = console.ask_confirm(&format!(
+        "Are you sure you want to remove the OTP secret of \"{label}\""
+    ));
+
+    if !confirmed {
+        bail!(HandlingError::OtpRemoveAborted);
+    }
+
+    data.otp = None;
+    data.modification_date = Some(Utc::now());
+
+    modified.update(&data)?;
+
+    console.success(&format!("OTP secret of \"{label}\" removed !"));
+
+    Ok(())
+}
+
+/// Clears the clipboard after a while, if it still holds the value whose digest
+/// is read from the standard input (see `Clipboard::clear_later()`).
+///
+/// This will return an error if :
+/// - the digest cannot be read.
+/// - the clipboard cannot be operated.
+fn clear_clipboard(cli: &Cli, after_secs: u64) -> Result<()> {
+    let clipboard = get_clipboard(cli)?;
+
+    let mut digest = String::new();
+    io::stdin().lock().read_line(&mut digest)?;
+
+    thread::sleep(Duration::from_secs(after_secs));
+
+    clipboard.clear_if_unchanged(digest.trim())?;
+
+    Ok(())
+}
+
+/// Reports the passwords expired or expiring within a number of days, the ones
+/// expiring first being listed first.
+///
+/// This will return an error if any password is expired or expiring, so that the
+/// report can be checked from the exit code (e.g. in cron jobs).
+fn expiring<State: Opened>(opened: &DataStore<State>, within_days: i64) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let today = Local::now().date_naive();
+    let expiring = opened.expiring(today, within_days);
+
+    if expiring.is_empty() {
+        console.success(&format!(
+            "No password expires within {within_days} day(s) !"
+        ));
+        return Ok(());
+    }
+
+    let headers: Vec<String> = vec![
+        "Label".to_string(),
+        "Url".to_string(),
+        "Login".to_string(),
+        "Expiration date".to_string(),
+    ];
+
+    let lines: Vec<Vec<String>> = expiring
+        .iter()
+        .map(|data| {
+            vec![
+                data.label.clone(),
+                sanitize_none_option_string(data.url.clone()),
+                sanitize_none_option_string(data.login.clone()),
+                format_expiration_date(data, today, within_days),
+            ]
+        })
+        .collect();
+
+    console.string_table(headers, lines);
+
+    bail!(HandlingError::PasswordsExpiring(
+        expiring.len(),
+        within_days
+    ));
+}
+
+/// Generates a strong random password of 24 chars.
+///
+/// This will return an error if the password cannot be generated.
+fn generate<State: Opened>(_opened: &DataStore<State>) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let generated = passwords::generate(24)?;
+
+    console.success(&format!("Password generated: {generated}"));
+
+    Ok(())
+}
+
+/// Lists the previous passwords of a password given its label.
+///
+/// Passwords are masked unless `reveal` is set.
+///
+/// This will return an error if the password label is not found in the DataStore.
+fn history<State: Opened>(opened: &DataStore<State>, label: &str, reveal: bool) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let Ok(data) = opened.get(label) else {
+        bail!(HandlingError::KeyNotFound(label.into()));
+    };
+
+    if data.history.is_empty() {
+        console.info(&format!("No previous password for entry \"{label}\""));
+        return Ok(());
+    }
+
+    let headers: Vec<String> = vec![
+        "Version".to_string(),
+        "Password".to_string(),
+        "Replacement date".to_string(),
+    ];
+
+    let mut lines: Vec<Vec<String>> = vec![];
+
+    for (index, previous) in data.history.iter().enumerate() {
+        let local_time: DateTime<Local> = DateTime::from(previous.replacement_date);
+
+        lines.push(vec![
+            (index + 1).to_string(),
+            if reveal {
+                previous.password.expose().to_string()
+            } else {
+                PASSWORD_MASK.to_string()
+            },
+            local_time.format("%v %X").to_string(),
+        ]);
+    }
+
+    console.string_table(headers, lines);
+
+    Ok(())
+}
+
+/// Restores a previous password given its label and its version in the history.
+///
+/// The replaced password is kept in the history, so the restoration can be undone.
+///
+/// This will return an error if :
+/// - the password label is not found in the DataStore.
+/// - the version is not found in the password history.
+/// - the restoration is not confirmed.
+fn restore(modified: &mut DataStore<Dirty>, label: &str, version: usize) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    if modified.get(label).is_err() {
+        bail!(HandlingError::KeyNotFound(label.into()));
+    }
+
+    let confirmed = console.ask_confirm(&format!(
+        "Are you sure you want to restore version {version} of entry \"{label}\""
+    ));
+
+    if !confirmed {
+        bail!(HandlingError::RestoreAborted);
+    }
+
+    modified.restore(label, version)?;
+
+    console.success(&format!(
+        "Version {version} of entry \"{label}\" restored !"
+    ));
+
+    Ok(())
+}
+
+/// Changes the master password of the DataStore, then saves it.
+///
+/// The new master password is asked twice, and its strength is checked.
+///
+/// This will return an error if :
+/// - the DataStore cannot be unlocked with the current master password.
+/// - the new master password cannot be read.
+/// - the new master password is the same as the current one.
+/// - the "unsafe master password" usage is not confirmed.
+/// - the DataStore cannot be saved.
+fn change_master(data_store: DataStore, master_password: &str) -> Result<DataStore> {
+    let console = ConsoleIO::new();
+
+    let opened = unlock(data_store, master_password)?;
+
+    let new_master_password = require_new_password(
+        &console,
+        "New master password:",
+        HandlingError::MasterPasswordChangeAborted,
+    )?;
+
+    if new_master_password.expose() == master_password {
+        bail!(HandlingError::SameMasterPassword);
+    }
+
+    let mut modified = opened.modify();
+    with_spinner("Changing master password...", || {
+        modified.change_master_password(new_master_password.expose())
+    })?;
+
+    let locked = modified.lock()?;
+
+    console.success("Master password changed !");
+    console.note(
+        "The datastore is now ciphered with a new key, but existing backups are still \
+         ciphered with the previous one, and can only be restored with the previous master password",
+    );
+
+    Ok(locked)
+}
+
+/// Calibrates the key derivation of the master password on the current machine.
+///
+/// The key derivation parameters lasting about `target_ms` are stored in the
+/// master password key slot, and used from the next unlock onwards.
+///
+/// This will return an error if :
+/// - the DataStore cannot be unlocked.
+/// - the key derivation cannot be benchmarked.
+/// - the DataStore key cannot be wrapped with the new parameters.
+fn calibrate_kdf(
+    data_store: DataStore,
+    target_ms: u64,
+    master_password: &str,
+) -> Result<DataStore<Dirty>> {
+    let console = ConsoleIO::new();
+
+    let opened = unlock(data_store, master_password)?;
+
+    let kdf_params = with_spinner("Calibrating key derivation...", || {
+        crypto::calibrate(Duration::from_millis(target_ms))
+    })?;
+
+    let mut modified = opened.modify();
+    let kdf_params = with_spinner("Wrapping datastore key...", || {
+        modified.change_kdf_params(master_password, kdf_params)
+    })?;
+
+    console.success(&format!("Key derivation calibrated: {kdf_params}"));
+
+    Ok(modified)
+}
+
+/// Starts the agent keeping the DataStore unlocked, until idle for `timeout` seconds.
+///
+/// The agent is detached from the terminal, unless it runs in the `foreground`.
+///
+/// This will return an error if :
+/// - an agent is already running for the DataStore.
+/// - the DataStore cannot be unlocked.
+/// - the agent cannot be started.
+fn start_agent(
+    data_store: DataStore,
+    agent: Option<AgentClient>,
+    timeout: u64,
+    foreground: bool,
+    cli: &Cli,
+) -> Result<()> {
+    if let Some(agent) = agent {
+        bail!(AgentError::AlreadyRunning(
+            agent.socket_path().to_path_buf()
+        ));
+    }
+
+    let console = ConsoleIO::new();
+
+    let master_password = get_master_password(cli)?;
+
+    if !foreground {
+        let pid = with_spinner("Starting agent...", || {
+            agent::spawn(
+                data_store.path(),
+                cli.keyfile.as_deref(),
+                &master_password,
+                timeout,
+            )
+        })?;
+
+        console.success(&format!(
+            "Agent started (PID {pid}), the datastore is locked after {timeout}s of inactivity or with `rpass lock`"
+        ));
+
+        return Ok(());
+    }
+
+    let opened = unlock(data_store, master_password.expose())?;
+    drop(master_password);
+
+    agent::serve(opened, Duration::from_secs(timeout), |socket_path| {
+        console.success(&format!(
+            "Agent listening on {}, the datastore is locked after {timeout}s of inactivity or with `rpass lock`",
+            socket_path.display()
+        ));
+    })
+}
+
+/// Locks the DataStore held by the agent, and stops it.
+///
+/// This will return an error if the agent cannot be reached.
+fn lock_agent(agent: Option<AgentClient>) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let Some(agent) = agent else {
+        console.info("No agent is running for this datastore");
+        return Ok(());
+    };
+
+    agent.lock()?;
+
+    console.success("Datastore locked !");
+
+    Ok(())
+}
+
+/// Lists the encrypted backups of the DataStore.
+///
+/// This will return an error if the backups cannot be read.
+fn list_backups(data_store: &DataStore) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let backups = data_store.backups()?;
+
+    if backups.is_empty() {
+        console.info("No backup found for this datastore");
+        return Ok(());
+    }
+
+    let headers: Vec<String> = vec![
+        "Version".to_string(),
+        "Date".to_string(),
+        "Size".to_string(),
+        "Path".to_string(),
+    ];
+
+    let lines: Vec<Vec<String>> = backups
+        .iter()
+        .map(|backup| {
+            let local_time: DateTime<Local> = DateTime::from(backup.modification_date);
+
+            vec![
+                backup.version.to_string(),
+                local_time.format("%v %X").to_string(),
+                format!("{} bytes", backup.size),
+                backup.path.display().to_string(),
+            ]
+        })
+        .collect();
+
+    console.string_table(headers, lines);
+
+    Ok(())
+}
+
+/// Restores an encrypted backup of the DataStore given its version.
+///
+/// The master password asked is the one of the backup, which may differ from the
+/// current one. The replaced DataStore becomes the most recent backup.
+///
+/// This will return an error if :
+/// - the restoration is not confirmed.
+/// - the master password cannot be read.
+/// - the backup cannot be found or unlocked.
+/// - the backup cannot be written over the DataStore.
+fn restore_backup(data_store: &DataStore, version: usize, cli: &Cli) -> Result<()> {
+    let console = ConsoleIO::new();
+
+    let confirmed = console.ask_confirm(&format!(
+        "Are you sure you want to replace the datastore with backup {version}"
+    ));
+
+    if !confirmed {
+        bail!(HandlingError::BackupRestoreAborted);
+    }
+
+    let master_password = get_master_password(cli)?;
+
+    with_spinner("Unlocking backup...", || {
+        data_store.restore_backup(version, master_password.expose())
+    })?;
+
+    console.success(&format!("Backup {version} restored !"));
+
+    Ok(())
+}
+
+///////////////////// UTILITY FUNCTIONS /////////////////////
+
+/// Unlocks the DataStore through the agent if it is running, or with the master password.
+///
+/// This will return an error if :
+/// - the agent cannot be reached.
+/// - the master password cannot be read.
+/// - the DataStore cannot be unlocked.
+fn open(
+    data_store: DataStore,
+    agent: Option<AgentClient>,
+    cli: &Cli,
+) -> Result<DataStore<Unlocked>> {
+    match agent {
+        Some(agent) => data_store.unlock_with_agent(agent),
+        None => unlock(data_store, get_master_password(cli)?.expose()),
+    }
+}
+
+/// Picks the label of the password to operate on.
+///
+/// An exact label is used as is. Otherwise, the user picks the password among the ones
+/// matching the given name (or among all of them when no name is given), the best
+/// matches first. Nothing is picked outside of a terminal, e.g. in command chains.
+///
+/// This will return an error if :
+/// - no password matches the given name.
+/// - the password cannot be picked.
+fn pick_label<State: Opened>(opened: &DataStore<State>, name: Option<&str>) -> Result<String> {
+    if let Some(name) = name {
+        if opened.get(name).is_ok() {
+            return Ok(name.to_string());
+        }
+    }
+
+    let candidates: Vec<&PasswordStore> = match name {
+        Some(name) => opened.search(name),
+        None => opened
+            .data()
+            .values()
+            .sorted_by_key(|data| &data.label)
+            .collect(),
+    };
+
+    let not_found = || match name {
+        Some(name) => HandlingError::KeyNotFound(name.into()),
+        None if candidates.is_empty() => HandlingError::EmptyDataStore,
+        None => HandlingError::NameRequired,
+    };
+
+    if candidates.is_empty() || !io::stdin().is_terminal() {
+        bail!(not_found());
+    }
+
+    let choices: Vec<String> = candidates
+        .iter()
+        .map(|data| match &data.login {
+            Some(login) => format!("{} ({login})", data.label),
+            None => data.label.clone(),
+        })
+        .collect();
+
+    let question = match name {
+        Some(name) => format!("Passwords matching \"{name}\":"),
+        None => "Passwords:".to_string(),
+    };
+
+    let console = ConsoleIO::new();
+
+    let picked = console
+        .input_select(&question, choices.iter().map(String::as_str).collect())
+        .raw_prompt()?;
+
+    Ok(candidates[picked.index].label.clone())
+}
+
+/// Checks that no agent holds the DataStore, for commands operating on the DataStore file.
+///
+/// This will return an error if the agent is running.
+fn require_no_agent(agent: &Option<AgentClient>) -> Result<()> {
+    if agent.is_some() {
+        bail!(DataStoreError::HeldByAgent);
+    }
+
+    Ok(())
+}
+
+/// Unlocks the DataStore, showing a spinner while the master password key is derived.
+///
+/// This will return an error if the DataStore cannot be unlocked.
+fn unlock(data_store: DataStore, master_password: &str) -> Result<DataStore<Unlocked>> {
+    with_spinner("Unlocking datastore...", || {
+        data_store.unlock(master_password)
+    })
+}
+
+/// Runs a long operation (e.g. a key derivation) while a spinner shows its progress.
+///
+/// The spinner is cleared once the operation is done, whatever its result.
+fn with_spinner<T>(message: &str, operation: impl FnOnce() -> Result<T>) -> Result<T> {
+    let console = ConsoleIO::new();
+
+    let spinner = console.create_spinner();
+    spinner.set_message(message.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+
+    let result = operation();
+
+    spinner.finish_and_clear();
+
+    result
+}
+
+/// Loads the keyfile given in the CLI arguments, if any.
+///
+/// Upon initialization, a missing keyfile is generated.
+///
+/// This will return an error if the keyfile cannot be generated or read.
+fn get_keyfile(cli: &Cli, generate_if_missing: bool) -> Result<Option<Keyfile>> {
+    let Some(path) = &cli.keyfile else {
+        return Ok(None);
+    };
+
+    if generate_if_missing && !path.try_exists()? {
+        let keyfile = Keyfile::generate(path)?;
+
+        let console = ConsoleIO::new();
+        console.warning(&format!(
+            "Keyfile generated in {}, keep it safe: it is required along the master password to unlock the datastore",
+            path.display()
+        ));
+
+        return Ok(Some(keyfile));
+    }
+
+    Ok(Some(Keyfile::load(path)?))
+}
+
+/// Asks either a password expires, and its expiration date if so.
+///
+/// The current expiration date (if any) is proposed by default.
+///
+/// This will return an error if the answers cannot be read.
+fn ask_expiration_date(
+    console: &ConsoleIO,
+    current: Option<NaiveDate>,
+) -> Result<Option<NaiveDate>> {
+    let expires = console
+        .input_confirm("Does this password expire")
+        .with_default(current.is_some())
+        .prompt()?;
+
+    if !expires {
+        return Ok(None);
+    }
+
+    let date = console
+        .input_date("Expiration date:")
+        .with_starting_date(current.unwrap_or_else(|| Local::now().date_naive()))
+        .prompt()?;
+
+    Ok(Some(date))
+}
+
+/// Formats the expiration date of a password, flagged once expired or when
+/// expiring within `warning_days`.
+fn format_expiration_date(data: &PasswordStore, today: NaiveDate, warning_days: i64) -> String {
+    let (Some(date), Some(days)) = (data.expiration_date, data.days_before_expiration(today))
+    else {
+        return "".into();
+    };
+
+    let date = date.format("%v");
+
+    match days {
+        ..=0 => format!("⛔ {date} (expired)"),
+        days if days <= warning_days => format!("⏳ {date} (in {days} day(s))"),
+        _ => date.to_string(),
+    }
+}
+
+/// Gets the clipboard, operated by the configured commands or by the detected ones.
+///
+/// This will return an error if no clipboard command is configured nor found.
+fn get_clipboard(cli: &Cli) -> Result<Clipboard> {
+    Clipboard::new(
+        cli.clipboard_command.as_deref(),
+        cli.clipboard_paste_command.as_deref(),
+    )
+}
+
+/// Gets the master password from the source given in the CLI arguments, or requests it to the user.
+///
+/// A warning is displayed when the master password is given as a command line argument.
+///
+/// This will return an error if the password cannot be read.
+fn get_master_password(cli: &Cli) -> Result<SecretString> {
+    let Some(source) = PasswordSource::from_cli(cli) else {
+        return require_master_password();
+    };
+
+    if let PasswordSource::Argument(_) = source {
+        let console = ConsoleIO::new();
+        console.warning(
+            "The master password given with --master-password is visible by other processes and kept in shell history, prefer --password-stdin, --password-file, --password-fd or RPASS_PASSWORD_COMMAND",
+        );
+    }
+
+    source.read()
+}
+
+/// Requests the master password to unlock a DataStore.
+///
+/// This will return an error if the password cannot be read.
+fn require_master_password() -> Result<SecretString> {
+    let console_utils = ConsoleIO::new();
+
+    let password_question = console_utils.input_password("Enter master password:");
+
+    let master_password = password_question
+        .without_confirmation()
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .prompt()?;
+
+    Ok(master_password.into())
+}
+
+/// Requests a new password and displays its strength.
+///
+/// If the password is not considered safe, the user is asked to confirm its usage,
+/// otherwise the given `abort_error` is returned.
+///
+/// This will return an error if :
+/// - the password cannot be read.
+/// - the password strength cannot be calculated.
+/// - the "unsafe password" usage is not confirmed.
+fn require_new_password(
+    console: &ConsoleIO,
+    question: &str,
+    abort_error: HandlingError,
+) -> Result<SecretString> {
+    let password = SecretString::from(
+        console
+            .input_password(question)
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_validator(required!())
+            .prompt()?,
+    );
+
+    confirm_password_strength(console, &password, abort_error)?;
+
+    Ok(password)
+}
+
+/// Prints the strength of a new password, and asks for confirmation if it is weak.
+///
+/// This will return an error if :
+/// - the password strength cannot be calculated.
+/// - the "unsafe password" is not confirmed.
+fn confirm_password_strength(
+    console: &ConsoleIO,
+    password: &SecretString,
+    abort_error: HandlingError,
+) -> Result<()> {
+    let password_strength_label = passwords::format_password_strength(password.expose())?;
+    console.writeln(&format!("Password strength: {password_strength_label}"));
+
+    if passwords::get_password_strength(password.expose())? < 3 {
+        let confirmed = console.ask_confirm(
+            "Your password seems to be not safe enough, are you sure you want to store it as it is",
+        );
+
+        if !confirmed {
+            bail!(abort_error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks the new secret of an entry, named and checked according to its kind.
+///
+/// Login passwords are asked as by `require_new_password()`. The other secrets are
+/// validated by their kind, and only confirmed and checked for strength if they
+/// are passwords (e.g. Wi-Fi keys), notes being typed in full on several lines.
+/// Entries without any secret get an empty one.
+///
+/// This will return an error if :
+/// - the secret cannot be read.
+/// - the password strength cannot be calculated.
+/// - the "unsafe password" is not confirmed.
+fn require_new_secret(
+    console: &ConsoleIO,
+    kind: &EntryKind,
+    abort_error: HandlingError,
+) -> Result<SecretString> {
+    if *kind == EntryKind::Login {
+        return require_new_password(console, "Password:", abort_error);
+    }
+
+    if !kind.has_secret() {
+        return Ok(SecretString::default());
+    }
+
+    let question = format!("{}:", kind.secret_name());
+
+    // a note is typed in full, on several lines
+    if *kind == EntryKind::SecureNote {
+        loop {
+            let note = SecretString::from(console.ask_multiline(&question)?);
+
+            match kind.validate_secret(note.expose()) {
+                Ok(()) => return Ok(note),
+                Err(error) => console.error(&error.to_string()),
+            }
+        }
+    }
+
+    let validated_kind = kind.clone();
+    let mut prompt = console
+        .input_password(&question)
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .with_validator(entry_validator(move |input| {
+            validated_kind.validate_secret(input)
+        }));
+
+    if !kind.checks_strength() {
+        prompt = prompt.without_confirmation();
+    }
+
+    let secret = SecretString::from(prompt.prompt()?);
+
+    if kind.checks_strength() {
+        confirm_password_strength(console, &secret, abort_error)?;
+    }
+
+    Ok(secret)
+}
+
+/// Asks the fields specific to a kind of entry, prefilled with its current ones.
+///
+/// The number of a card is never displayed, and kept as it is if left empty.
+///
+/// This will return an error if the fields cannot be read.
+fn ask_kind_details(console: &ConsoleIO, kind: &EntryKind) -> Result<EntryKind> {
+    let kind = match kind {
+        EntryKind::Login | EntryKind::SecureNote => kind.clone(),
+        EntryKind::Card(card) => {
+            let cardholder = console
+                .input_text("Cardholder name:")
+                .with_initial_value(&card.cardholder)
+                .with_validator(required!())
+                .prompt()?;
+
+            let has_number = !card.number.expose().is_empty();
+            let mut prompt = console
+                .input_password("Card number:")
+                .without_confirmation()
+                .with_display_mode(PasswordDisplayMode::Masked)
+                .with_validator(entry_validator(move |input| match input.is_empty() {
+                    true if has_number => Ok(()),
+                    _ => entry::validate_card_number(input),
+                }));
+
+            if has_number {
+                prompt = prompt.with_help_message("Leave empty to keep the current number");
+            }
+
+            let number = SecretString::from(prompt.prompt()?);
+            let number = match number.expose().is_empty() {
+                true => card.number.clone(),
+                false => entry::normalize_card_number(number.expose()).into(),
+            };
+
+            let expiry = console
+                .input_text("Card expiry (MM/YY):")
+                .with_initial_value(&card.expiry)
+                .with_validator(entry_validator(entry::validate_card_expiry))
+                .prompt()?;
+
+            EntryKind::Card(CardDetails {
+                cardholder,
+                number,
+                expiry,
+            })
+        }
+        EntryKind::Identity(identity) => {
+            let full_name = console
+                .input_text("Full name:")
+                .with_initial_value(&identity.full_name)
+                .with_validator(required!())
+                .prompt()?;
+
+            let has_birth_date = console
+                .input_confirm("Do you want to record the birth date")
+                .with_default(identity.birth_date.is_some())
+                .prompt()?;
+
+            let birth_date = match has_birth_date {
+                true => Some(
+                    console
+                        .input_date("Birth date:")
+                        .with_starting_date(
+                            identity
+                                .birth_date
+                                .unwrap_or_else(|| Local::now().date_naive()),
+                        )
+                        .prompt()?,
+                ),
+                false => None,
+            };
+
+            let email = console
+                .input_text("Email:")
+                .with_initial_value(&sanitize_none_option_string(identity.email.clone()))
+                .with_validator(entry_validator(entry::validate_email))
+                .prompt()?;
+            let phone = console
+                .input_text("Phone:")
+                .with_initial_value(&sanitize_none_option_string(identity.phone.clone()))
+                .prompt()?;
+            let address = console
+                .input_text("Address:")
+                .with_initial_value(&sanitize_none_option_string(identity.address.clone()))
+                .prompt()?;
+
+            EntryKind::Identity(IdentityDetails {
+                full_name,
+                birth_date,
+                email: if email.is_empty() { None } else { Some(email) },
+                phone: if phone.is_empty() { None } else { Some(phone) },
+                address: if address.is_empty() {
+                    None
+                } else {
+                    Some(address)
+                },
+            })
+        }
+        EntryKind::Wifi(wifi) => {
+            let ssid = console
+                .input_text("SSID (network name):")
+                .with_initial_value(&wifi.ssid)
+                .with_validator(entry_validator(entry::validate_ssid))
+                .prompt()?;
+
+            let securities = WifiSecurity::all();
+            let names: Vec<String> = securities.iter().map(ToString::to_string).collect();
+            let picked = console
+                .input_select("Security:", names.iter().map(String::as_str).collect())
+                .with_starting_cursor(
+                    securities
+                        .iter()
+                        .position(|security| *security == wifi.security)
+                        .unwrap_or(0),
+                )
+                .raw_prompt()?;
+
+            EntryKind::Wifi(WifiDetails {
+                ssid,
+                security: securities[picked.index],
+            })
+        }
+    };
+
+    Ok(kind)
+}
+
+/// Asks the custom fields of a password, starting from its current ones.
+///
+/// Each current field is kept, changed or removed, then new fields are asked
+/// until the user is done. The values of secret fields are masked while typed.
+///
+/// This will return an error if the fields cannot be read.
+fn ask_custom_fields(console: &ConsoleIO, current: Vec<CustomField>) -> Result<Vec<CustomField>> {
+    let mut fields = vec![];
+
+    for field in current {
+        let question = format!("Custom field \"{}\":", field.name);
+        let action = console
+            .input_select(&question, vec!["Keep", "Change", "Remove"])
+            .raw_prompt()?;
+
+        match action.index {
+            0 => fields.push(field),
+            1 => fields.push(ask_custom_field(console, field.name.clone(), Some(&field))?),
+            _ => {}
+        }
+    }
+
+    while console
+        .input_confirm("Do you want to add a custom field")
+        .with_default(false)
+        .prompt()?
+    {
+        let names: Vec<String> = fields.iter().map(|field| field.name.clone()).collect();
+
+        let name = console
+            .input_text("Field name:")
+            .with_validator(required!())
+            .with_validator(move |name: &str| {
+                Ok(match names.iter().any(|other| other == name) {
+                    true => Validation::Invalid("This field already exists".into()),
+                    false => Validation::Valid,
+                })
+            })
+            .prompt()?;
+
+        fields.push(ask_custom_field(console, name, None)?);
+    }
+
+    Ok(fields)
+}
+
+/// Asks whether a custom field is secret and its value, prefilled with its current
+/// one unless secret.
+///
+/// This will return an error if the field cannot be read.
+fn ask_custom_field(
+    console: &ConsoleIO,
+    name: String,
+    current: Option<&CustomField>,
+) -> Result<CustomField> {
+    let secret = console
+        .input_confirm("Is this field secret")
+        .with_default(current.is_some_and(|field| field.secret))
+        .prompt()?;
+
+    let question = format!("{name}:");
+    let value = match secret {
+        true => console
+            .input_password(&question)
+            .without_confirmation()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .prompt()?,
+        false => console
+            .input_text(&question)
+            .with_initial_value(
+                current
+                    .filter(|field| !field.secret)
+                    .map_or("", |field| field.value.expose()),
+            )
+            .prompt()?,
+    };
+
+    Ok(CustomField {
+        name,
+        value: value.into(),
+        secret,
+    })
+}
+
+/// Wraps a check of entry fields into a prompt validator, displaying its error.
+fn entry_validator(
+    check: impl Fn(&str) -> Result<(), EntryError> + Clone,
+) -> impl Fn(&str) -> Result<Validation, CustomUserError> + Clone {
+    move |input: &str| {
+        Ok(match check(input) {
+            Ok(()) => Validation::Valid,
+            Err(error) => Validation::Invalid(error.to_string().into()),
+        })
+    }
+}
+
+/// Formats the kind of an entry, along with the summary of its fields.
+fn format_kind(kind: &EntryKind) -> String {
+    match kind.summary() {
+        summary if summary.is_empty() => kind.to_string(),
+        summary => format!("{kind}: {summary}"),
+    }
 }
 
 /// Transforms an `Option<String>` into a `String` by filling a default empty string
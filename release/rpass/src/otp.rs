@@ -0,0 +1,460 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+use hmac::{digest::KeyInit, Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::secret::SecretString;
+
+/// Alphabet of the base32 encoding (RFC 4648), in which OTP secrets are shared.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Scheme of the URIs sharing OTP secrets (e.g. in QR codes).
+const OTPAUTH_SCHEME: &str = "otpauth://";
+
+/// Default number of digits of the codes.
+const DEFAULT_DIGITS: u32 = 6;
+
+/// Default validity of time-based codes, in seconds.
+const DEFAULT_PERIOD: u64 = 30;
+
+/// Possible errors upon OTP secrets parsing and code generation.
+#[derive(Debug, Error)]
+pub enum OtpError {
+    #[error("Invalid OTP secret, expected an otpauth:// URI or a base32 string")]
+    InvalidSecret,
+    #[error("Invalid otpauth:// URI: {0}")]
+    InvalidUri(String),
+    #[error("OTP algorithm \"{0}\" is not supported, use SHA1, SHA256 or SHA512")]
+    UnsupportedAlgorithm(String),
+    #[error("OTP codes must have between 6 and 8 digits")]
+    InvalidDigits,
+    #[error("OTP period must be at least 1 second")]
+    InvalidPeriod,
+    #[error("OTP counter has reached its maximum value, the secret must be renewed")]
+    CounterOverflow,
+}
+
+/// Hash functions usable to compute the codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// Kinds of one-time passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtpKind {
+    /// Time-based codes (RFC 6238), each one valid for a period in seconds.
+    Totp { period: u64 },
+    /// Counter-based codes (RFC 4226), the counter being incremented for each code.
+    Hotp { counter: u64 },
+}
+
+/// Representation of a two-factor secret, generating one-time passwords.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OtpSecret {
+    /// The shared secret, encoded in base32 (uppercase, without padding).
+    pub secret: SecretString,
+    /// The hash function used to compute the codes.
+    pub algorithm: OtpAlgorithm,
+    /// The number of digits of the codes.
+    pub digits: u32,
+    /// The kind of codes, and its moving factor.
+    pub kind: OtpKind,
+}
+
+/// A generated one-time password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpCode {
+    /// The code itself, padded with zeros.
+    pub code: String,
+    /// The time during which a time-based code remains valid, in seconds.
+    pub remaining_secs: Option<u64>,
+}
+
+impl OtpSecret {
+    /// Parses an OTP secret, either an otpauth:// URI or a base32 string.
+    ///
+    /// A base32 string is a time-based secret with the default parameters
+    /// (SHA1, 6 digits, 30 seconds).
+    ///
+    /// This will return an error if :
+    /// - the secret is not valid base32.
+    /// - the URI is malformed, or has unsupported parameters.
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+
+        if input
+            .get(..OTPAUTH_SCHEME.len())
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case(OTPAUTH_SCHEME))
+        {
+            return Self::parse_uri(&input[OTPAUTH_SCHEME.len()..]);
+        }
+
+        Self::new(
+            input,
+            OtpAlgorithm::Sha1,
+            DEFAULT_DIGITS,
+            OtpKind::Totp {
+                period: DEFAULT_PERIOD,
+            },
+        )
+    }
+
+    /// Creates an OTP secret from a base32 string.
+    ///
+    /// This will return an error if :
+    /// - the secret is not valid base32.
+    /// - the number of digits is not between 6 and 8.
+    /// - the period of time-based codes is zero.
+    pub fn new(secret: &str, algorithm: OtpAlgorithm, digits: u32, kind: OtpKind) -> Result<Self> {
+        let secret: Zeroizing<String> = Zeroizing::new(
+            secret
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+                .map(|c| c.to_ascii_uppercase())
+                .collect(),
+        );
+
+        if decode_base32(&secret)?.is_empty() {
+            bail!(OtpError::InvalidSecret);
+        }
+
+        if !(6..=8).contains(&digits) {
+            bail!(OtpError::InvalidDigits);
+        }
+
+        if kind == (OtpKind::Totp { period: 0 }) {
+            bail!(OtpError::InvalidPeriod);
+        }
+
+        Ok(Self {
+            secret: SecretString::from(secret.as_str()),
+            algorithm,
+            digits,
+            kind,
+        })
+    }
+
+    /// Generates the current code: the one of the current time period for time-based
+    /// secrets, or the one of the current counter for counter-based secrets, whose
+    /// counter is then incremented.
+    ///
+    /// This will return an error if :
+    /// - the secret is not valid base32.
+    /// - the counter cannot be incremented anymore.
+    pub fn generate(&mut self, timestamp: u64) -> Result<OtpCode> {
+        match &mut self.kind {
+            OtpKind::Totp { period } => {
+                let period = *period;
+
+                Ok(OtpCode {
+                    code: self.code_at(timestamp / period)?,
+                    remaining_secs: Some(period - timestamp % period),
+                })
+            }
+            OtpKind::Hotp { counter } => {
+                let current = *counter;
+
+                let Some(next) = current.checked_add(1) else {
+                    bail!(OtpError::CounterOverflow);
+                };
+                *counter = next;
+
+                Ok(OtpCode {
+                    code: self.code_at(current)?,
+                    remaining_secs: None,
+                })
+            }
+        }
+    }
+
+    /// Computes the code of a given moving factor (a counter, or a number of time periods),
+    /// as defined by RFC 4226.
+    ///
+    /// This will return an error if the secret is not valid base32.
+    pub fn code_at(&self, moving_factor: u64) -> Result<String> {
+        let key = decode_base32(self.secret.expose())?;
+        let mac = hmac(self.algorithm, &key, &moving_factor.to_be_bytes())?;
+
+        // dynamic truncation: 31 bits read at an offset given by the last 4 bits
+        let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            mac[offset] & 0x7f,
+            mac[offset + 1],
+            mac[offset + 2],
+            mac[offset + 3],
+        ]);
+
+        let code = binary % 10u32.pow(self.digits);
+
+        Ok(format!("{code:0width$}", width = self.digits as usize))
+    }
+
+    /// Parses the part of an otpauth:// URI following its scheme,
+    /// e.g. `totp/Issuer:login?secret=...&issuer=...&algorithm=SHA1&digits=6&period=30`.
+    ///
+    /// This will return an error if the URI is malformed, or has unsupported parameters.
+    fn parse_uri(uri: &str) -> Result<Self> {
+        let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+        let kind = path.split('/').next().unwrap_or_default().to_lowercase();
+
+        let mut secret = None;
+        let mut algorithm = OtpAlgorithm::Sha1;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+
+        let invalid = |parameter: &str| OtpError::InvalidUri(format!("invalid {parameter}"));
+
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            let value = Zeroizing::new(percent_decode(value));
+
+            match name.to_lowercase().as_str() {
+                "secret" => secret = Some(value),
+                "algorithm" => {
+                    algorithm = match value.to_uppercase().as_str() {
+                        "SHA1" => OtpAlgorithm::Sha1,
+                        "SHA256" => OtpAlgorithm::Sha256,
+                        "SHA512" => OtpAlgorithm::Sha512,
+                        _ => bail!(OtpError::UnsupportedAlgorithm(value.to_string())),
+                    }
+                }
+                "digits" => digits = value.parse().map_err(|_| invalid("digits"))?,
+                "period" => period = value.parse().map_err(|_| invalid("period"))?,
+                "counter" => counter = Some(value.parse().map_err(|_| invalid("counter"))?),
+                // the label and issuer are already known from the password itself
+                _ => {}
+            }
+        }
+
+        let Some(secret) = secret else {
+            bail!(OtpError::InvalidUri("missing secret".into()));
+        };
+
+        let kind = match kind.as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter.ok_or_else(|| OtpError::InvalidUri("missing counter".into()))?,
+            },
+            _ => bail!(OtpError::InvalidUri(format!("unknown type \"{kind}\""))),
+        };
+
+        Self::new(&secret, algorithm, digits, kind)
+    }
+}
+
+/// Describes the parameters of a secret, without revealing it.
+impl fmt::Display for OtpSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let algorithm = match self.algorithm {
+            OtpAlgorithm::Sha1 => "SHA1",
+            OtpAlgorithm::Sha256 => "SHA256",
+            OtpAlgorithm::Sha512 => "SHA512",
+        };
+
+        match self.kind {
+            OtpKind::Totp { period } => write!(
+                f,
+                "TOTP ({algorithm}, {} digits, every {period}s)",
+                self.digits
+            ),
+            OtpKind::Hotp { counter } => write!(
+                f,
+                "HOTP ({algorithm}, {} digits, counter {counter})",
+                self.digits
+            ),
+        }
+    }
+}
+
+/// Decodes a base32 string (RFC 4648), ignoring its case and padding.
+///
+/// This will return an error if the string contains characters out of the base32 alphabet.
+fn decode_base32(encoded: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let mut decoded = Zeroizing::new(Vec::with_capacity(encoded.len() * 5 / 8));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in encoded.bytes().filter(|c| *c != b'=') {
+        let Some(value) = BASE32_ALPHABET
+            .iter()
+            .position(|letter| *letter == c.to_ascii_uppercase())
+        else {
+            bail!(OtpError::InvalidSecret);
+        };
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes the percent-encoded characters of a URI component (e.g. `%3D` for `=`).
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Computes the HMAC (RFC 2104) of a message with a given hash function.
+///
+/// ⚠️ SHA-1 is only used by HMAC-SHA1, as required by most OTP secrets, and must not be
+/// used for anything else. ⚠️
+fn hmac(algorithm: OtpAlgorithm, key: &[u8], message: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    match algorithm {
+        OtpAlgorithm::Sha1 => hmac_with::<Hmac<Sha1>>(key, message),
+        OtpAlgorithm::Sha256 => hmac_with::<Hmac<Sha256>>(key, message),
+        OtpAlgorithm::Sha512 => hmac_with::<Hmac<Sha512>>(key, message),
+    }
+}
+
+/// Computes the HMAC of a message with a given HMAC implementation.
+fn hmac_with<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let Ok(mut mac) = <M as Mac>::new_from_slice(key) else {
+        bail!(OtpError::InvalidSecret);
+    };
+
+    mac.update(message);
+
+    Ok(Zeroizing::new(mac.finalize().into_bytes().to_vec()))
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "12345678901234567890", the seed of the RFC 4226 and RFC 6238 test vectors, in base32.
+    const RFC_SEED_SHA1: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    /// The 32 bytes seed of the RFC 6238 test vectors with SHA256, in base32.
+    const RFC_SEED_SHA256: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA====";
+
+    /// The 64 bytes seed of the RFC 6238 test vectors with SHA512, in base32.
+    const RFC_SEED_SHA512: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA=";
+
+    /// test function for the HOTP codes (RFC 4226 test vectors)
+    #[test]
+    fn assert_hotp_codes() {
+        let mut secret = OtpSecret::new(
+            RFC_SEED_SHA1,
+            OtpAlgorithm::Sha1,
+            6,
+            OtpKind::Hotp { counter: 0 },
+        )
+        .unwrap();
+
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+
+        for code in expected {
+            assert_eq!(code, secret.generate(0).unwrap().code);
+        }
+
+        assert_eq!(OtpKind::Hotp { counter: 10 }, secret.kind);
+
+        // the last counter is never wrapped around to 0
+        secret.kind = OtpKind::Hotp { counter: u64::MAX };
+        assert!(secret.generate(0).is_err());
+        assert_eq!(OtpKind::Hotp { counter: u64::MAX }, secret.kind);
+    }
+
+    /// test function for the TOTP codes (RFC 6238 test vectors)
+    #[test]
+    fn assert_totp_codes() {
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+
+        let totp = |seed: &str, algorithm: OtpAlgorithm| {
+            OtpSecret::new(seed, algorithm, 8, OtpKind::Totp { period: 30 }).unwrap()
+        };
+
+        let mut sha1 = totp(RFC_SEED_SHA1, OtpAlgorithm::Sha1);
+        let mut sha256 = totp(RFC_SEED_SHA256, OtpAlgorithm::Sha256);
+        let mut sha512 = totp(RFC_SEED_SHA512, OtpAlgorithm::Sha512);
+
+        for (timestamp, code_sha1, code_sha256, code_sha512) in vectors {
+            assert_eq!(code_sha1, sha1.generate(timestamp).unwrap().code);
+            assert_eq!(code_sha256, sha256.generate(timestamp).unwrap().code);
+            assert_eq!(code_sha512, sha512.generate(timestamp).unwrap().code);
+        }
+
+        assert_eq!(Some(1), sha1.generate(59).unwrap().remaining_secs);
+        assert_eq!(Some(30), sha1.generate(60).unwrap().remaining_secs);
+    }
+
+    /// test function for the parsing of base32 secrets and otpauth:// URIs
+    #[test]
+    fn assert_secrets_are_parsed() {
+        let secret = OtpSecret::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+        assert_eq!(RFC_SEED_SHA1, secret.secret.expose());
+        assert_eq!(OtpKind::Totp { period: 30 }, secret.kind);
+        assert_eq!("TOTP (SHA1, 6 digits, every 30s)", secret.to_string());
+
+        let secret = OtpSecret::parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA%3D%3D%3D%3D&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(OtpAlgorithm::Sha256, secret.algorithm);
+        assert_eq!(8, secret.digits);
+        assert_eq!(OtpKind::Totp { period: 60 }, secret.kind);
+
+        let secret =
+            OtpSecret::parse("OTPAUTH://hotp/bank?secret=GEZDGNBVGY3TQOJQ&counter=42").unwrap();
+        assert_eq!("HOTP (SHA1, 6 digits, counter 42)", secret.to_string());
+
+        assert!(OtpSecret::parse("not base32 !").is_err());
+        assert!(OtpSecret::parse("").is_err());
+        assert!(OtpSecret::parse("otpauth://totp/bank?issuer=bank").is_err());
+        assert!(OtpSecret::parse("otpauth://hotp/bank?secret=GEZDGNBV").is_err());
+        assert!(OtpSecret::parse("otpauth://motp/bank?secret=GEZDGNBV").is_err());
+        assert!(OtpSecret::parse("otpauth://totp/bank?secret=GEZDGNBV&algorithm=MD5").is_err());
+        assert!(OtpSecret::parse("otpauth://totp/bank?secret=GEZDGNBV&digits=4").is_err());
+        assert!(OtpSecret::parse("otpauth://totp/bank?secret=GEZDGNBV&period=0").is_err());
+    }
+}
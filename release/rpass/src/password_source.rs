@@ -0,0 +1,264 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Cursor, Read},
+    path::PathBuf,
+    process::Stdio,
+};
+
+use anyhow::{bail, Result};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    cli::Cli, constants::RPASS_MASTER_PASSWORD_ENV, secret::SecretString, utils::shell_command,
+};
+
+/// Capacity reserved to read a master password, so that it is never reallocated (and copied).
+const PASSWORD_BUFFER_CAPACITY: usize = 1024;
+
+/// Possible errors while reading the master password from a non-interactive source.
+#[derive(Debug, Error)]
+pub enum PasswordSourceError {
+    #[error("Master password read from {0} is empty")]
+    Empty(String),
+    #[error("Master password cannot be read from {0}: {1}")]
+    Unreadable(String, String),
+    #[error("Master password command failed ({0})")]
+    CommandFailed(String),
+    #[error("{RPASS_MASTER_PASSWORD_ENV} environment variable is not set")]
+    EnvNotSet,
+}
+
+/// Non-interactive sources of the master password.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordSource {
+    /// Command line argument (⚠️ visible by other processes and kept in shell history).
+    Argument(String),
+    /// First line of the standard input.
+    Stdin,
+    /// First line of a file.
+    File(PathBuf),
+    /// First line read from an inherited file descriptor.
+    Fd(i32),
+    /// `RPASS_MASTER_PASSWORD` environment variable, once explicitly opted into.
+    Env,
+    /// First line of the standard output of a shell command (e.g. a secret manager).
+    Command(String),
+}
+
+impl PasswordSource {
+    /// Gets the master password source given in the CLI arguments, if any.
+    ///
+    /// Explicit options prevail over the `RPASS_PASSWORD_COMMAND` environment variable,
+    /// and `None` means the master password must be requested to the user.
+    pub fn from_cli(cli: &Cli) -> Option<PasswordSource> {
+        if let Some(master_password) = &cli.master_password {
+            return Some(PasswordSource::Argument(master_password.clone()));
+        }
+
+        if cli.password_stdin {
+            return Some(PasswordSource::Stdin);
+        }
+
+        if let Some(path) = &cli.password_file {
+            return Some(PasswordSource::File(path.clone()));
+        }
+
+        if let Some(fd) = cli.password_fd {
+            return Some(PasswordSource::Fd(fd));
+        }
+
+        if cli.password_env {
+            return Some(PasswordSource::Env);
+        }
+
+        cli.password_command
+            .as_ref()
+            .map(|command| PasswordSource::Command(command.clone()))
+    }
+
+    /// Reads the master password from this source.
+    ///
+    /// Only the first line is read, without its line ending.
+    ///
+    /// This will return an error if :
+    /// - the source cannot be read.
+    /// - the password command fails.
+    /// - the master password is empty.
+    pub fn read(&self) -> Result<SecretString> {
+        let unreadable =
+            |error: io::Error| PasswordSourceError::Unreadable(self.to_string(), error.to_string());
+
+        let password = match self {
+            PasswordSource::Argument(master_password) => master_password.as_str().into(),
+            PasswordSource::Stdin => read_stdin_first_line().map_err(unreadable)?,
+            PasswordSource::File(path) => {
+                let file = File::open(path).map_err(unreadable)?;
+                read_first_line(file).map_err(unreadable)?
+            }
+            PasswordSource::Fd(fd) => {
+                read_first_line(open_fd(*fd).map_err(unreadable)?).map_err(unreadable)?
+            }
+            PasswordSource::Env => {
+                let Some(value) = std::env::var_os(RPASS_MASTER_PASSWORD_ENV) else {
+                    bail!(PasswordSourceError::EnvNotSet);
+                };
+
+                // child processes (e.g. the password command) must not inherit the secret
+                std::env::remove_var(RPASS_MASTER_PASSWORD_ENV);
+
+                let value = Zeroizing::new(value.into_encoded_bytes());
+                read_first_line(Cursor::new(value.as_slice())).map_err(unreadable)?
+            }
+            PasswordSource::Command(command) => {
+                let output = shell_command(command)
+                    .stdin(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .stdout(Stdio::piped())
+                    .output()
+                    .map_err(unreadable)?;
+
+                let stdout = Zeroizing::new(output.stdout);
+
+                if !output.status.success() {
+                    bail!(PasswordSourceError::CommandFailed(
+                        output.status.to_string()
+                    ));
+                }
+
+                read_first_line(Cursor::new(stdout.as_slice())).map_err(unreadable)?
+            }
+        };
+
+        if password.expose().is_empty() {
+            bail!(PasswordSourceError::Empty(self.to_string()));
+        }
+
+        Ok(password)
+    }
+}
+
+/// Human readable representation of the master password source.
+impl fmt::Display for PasswordSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordSource::Argument(_) => write!(f, "command line argument"),
+            PasswordSource::Stdin => write!(f, "standard input"),
+            PasswordSource::File(path) => write!(f, "file {}", path.display()),
+            PasswordSource::Fd(fd) => write!(f, "file descriptor {fd}"),
+            PasswordSource::Env => write!(f, "{RPASS_MASTER_PASSWORD_ENV} environment variable"),
+            PasswordSource::Command(_) => write!(f, "password command"),
+        }
+    }
+}
+
+/// Reads the first line of a reader into a secret string, without its line ending.
+///
+/// The reader is read byte-wise rather than through a `BufReader`, whose buffer would
+/// keep a copy of the password (and of what follows) without ever being zeroized.
+///
+/// This will return an error if the reader fails, or the line is not valid UTF-8.
+fn read_first_line(mut reader: impl Read) -> io::Result<SecretString> {
+    let mut line = Zeroizing::new(Vec::with_capacity(PASSWORD_BUFFER_CAPACITY));
+    let mut byte = Zeroizing::new([0; 1]);
+
+    loop {
+        match reader.read(&mut *byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    while line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    match String::from_utf8(std::mem::take(&mut *line)) {
+        Ok(line) => Ok(SecretString::from(line)),
+        Err(error) => {
+            drop(Zeroizing::new(error.into_bytes()));
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            ))
+        }
+    }
+}
+
+/// Reads the first line of the standard input, straight from its file descriptor to
+/// bypass the buffer of `io::stdin()`, which is never zeroized.
+#[cfg(unix)]
+fn read_stdin_first_line() -> io::Result<SecretString> {
+    use std::{mem::ManuallyDrop, os::unix::io::FromRawFd};
+
+    // SAFETY: the standard input is open for the whole process, and never closed here
+    let stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(libc::STDIN_FILENO) });
+
+    read_first_line(&*stdin)
+}
+
+/// Reads the first line of the standard input.
+#[cfg(not(unix))]
+fn read_stdin_first_line() -> io::Result<SecretString> {
+    read_first_line(io::stdin().lock())
+}
+
+/// Opens an inherited file descriptor, which is closed once read.
+#[cfg(unix)]
+fn open_fd(fd: i32) -> io::Result<File> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: the file descriptor is owned by rpass from now on, as given by the caller
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Opens an inherited file descriptor, which is not supported on this platform.
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file descriptors are only supported on unix platforms",
+    ))
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the first line reading of a master password
+    #[test]
+    fn assert_first_line_is_read() {
+        let password = read_first_line(Cursor::new("secret\r\nnext line\n")).unwrap();
+        assert_eq!("secret", password.expose());
+
+        let password = read_first_line(Cursor::new("with spaces ")).unwrap();
+        assert_eq!("with spaces ", password.expose());
+
+        // what follows the first line is left unread
+        let mut reader = Cursor::new("secret\nnext line\n");
+        read_first_line(&mut reader).unwrap();
+        assert_eq!(7, reader.position());
+
+        assert!(read_first_line(Cursor::new(b"\xff\n")).is_err());
+    }
+
+    /// test function for the master password command
+    #[cfg(unix)]
+    #[test]
+    fn assert_password_command_is_read() {
+        let source = PasswordSource::Command("printf 'secret\\n'".into());
+        assert_eq!("secret", source.read().unwrap().expose());
+
+        assert!(PasswordSource::Command("exit 1".into()).read().is_err());
+        assert!(PasswordSource::Command("true".into()).read().is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+
+use base64::{engine::general_purpose, Engine};
+use chacha20poly1305::aead::OsRng;
+use rand::RngCore;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::KEY_LENGTH;
+
+/// Placeholder displayed in place of a secret in debug outputs.
+const REDACTED: &str = "[REDACTED]";
+
+/// A secret string (e.g. a password).
+///
+/// - its memory is wiped upon drop.
+/// - its memory is locked in RAM (where possible), so it is never swapped to disk.
+/// - it is redacted in debug outputs.
+#[derive(Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    /// Exposes the secret string, to actually use it.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Takes ownership of a string, so that no copy of it is left in memory.
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        lock_memory(value.as_ptr(), value.capacity());
+
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self::from(value.to_string())
+    }
+}
+
+impl Clone for SecretString {
+    fn clone(&self) -> Self {
+        Self::from(self.expose())
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.expose())
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from(String::deserialize(deserializer)?))
+    }
+}
+
+/// A secret 256 bits key.
+///
+/// The key lives in its own heap allocation, so that it is never copied upon moves:
+/// - its memory is wiped upon drop.
+/// - its memory is locked in RAM (where possible), so it is never swapped to disk.
+/// - it is redacted in debug outputs.
+#[derive(PartialEq, Eq)]
+pub struct SecretKey(Box<[u8; KEY_LENGTH]>);
+
+impl SecretKey {
+    /// Generates a cryptographically secure random key.
+    pub fn random() -> Self {
+        let mut key = Self::default();
+        OsRng.fill_bytes(key.0.as_mut());
+
+        key
+    }
+
+    /// Copies a key from a slice, which must be `KEY_LENGTH` bytes long.
+    ///
+    /// Returns `None` if the slice has not the length of a key.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != KEY_LENGTH {
+            return None;
+        }
+
+        let mut key = Self::default();
+        key.0.copy_from_slice(bytes);
+
+        Some(key)
+    }
+
+    /// Exposes the secret key, to actually use it.
+    pub fn expose(&self) -> &[u8; KEY_LENGTH] {
+        &self.0
+    }
+}
+
+/// An all zeros key, to be replaced.
+impl Default for SecretKey {
+    fn default() -> Self {
+        let key = Box::new([0u8; KEY_LENGTH]);
+        lock_memory(key.as_ptr(), KEY_LENGTH);
+
+        Self(key)
+    }
+}
+
+impl Clone for SecretKey {
+    fn clone(&self) -> Self {
+        let mut key = Self::default();
+        key.0.copy_from_slice(self.expose());
+
+        key
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Serializes the key in base64 (e.g. to store the keys of attachments in the DataStore).
+impl Serialize for SecretKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = Zeroizing::new(general_purpose::STANDARD.encode(self.expose()));
+
+        serializer.serialize_str(&encoded)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = Zeroizing::new(String::deserialize(deserializer)?);
+
+        let bytes = Zeroizing::new(
+            general_purpose::STANDARD
+                .decode(encoded.as_bytes())
+                .map_err(de::Error::custom)?,
+        );
+
+        SecretKey::from_slice(&bytes)
+            .ok_or_else(|| de::Error::invalid_length(bytes.len(), &"a 32 bytes key"))
+    }
+}
+
+/// Hardens the current process against secrets leaks.
+///
+/// Core dumps are disabled, so that the memory of the process (and its secrets)
+/// is never written to disk upon crash, nor readable with a debugger on Linux.
+///
+/// This is done on a best effort basis, and failures are silently ignored.
+pub fn harden_process() {
+    #[cfg(unix)]
+    {
+        let no_core_dump = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+
+        // SAFETY: `setrlimit` only reads the `rlimit` it is given, which lives on the
+        // stack for the whole call.
+        unsafe {
+            libc::setrlimit(libc::RLIMIT_CORE, &no_core_dump);
+        }
+    }
+
+    // SAFETY: `PR_SET_DUMPABLE` takes a plain integer, no memory is passed to `prctl`.
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+}
+
+/// Locks a memory region in RAM, so it is never swapped to disk.
+///
+/// This is done on a best effort basis (e.g. within `RLIMIT_MEMLOCK`), and failures
+/// are silently ignored. Pages are never unlocked, as they may be shared with other secrets.
+fn lock_memory(address: *const u8, length: usize) {
+    if length == 0 {
+        return;
+    }
+
+    // SAFETY: `mlock` does not access the memory region, it only pins its pages,
+    // and the region is owned by a live secret of `length` bytes.
+    #[cfg(unix)]
+    unsafe {
+        libc::mlock(address.cast(), length);
+    }
+
+    #[cfg(not(unix))]
+    let _ = address;
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the redaction of secrets in debug outputs
+    #[test]
+    fn assert_secrets_are_redacted() {
+        let password = SecretString::from("hunter2");
+        let key = SecretKey::random();
+
+        assert_eq!("hunter2", password.expose());
+        assert_eq!(REDACTED, format!("{password:?}"));
+        assert_eq!(REDACTED, format!("{key:?}"));
+        assert_ne!(&[0u8; KEY_LENGTH], key.expose());
+        assert_eq!(key, key.clone());
+    }
+
+    /// test function for the serialization of secret strings
+    #[test]
+    fn assert_secret_string_serialization() {
+        let password = SecretString::from("hunter2");
+
+        let json = serde_json::to_string(&password).unwrap();
+        assert_eq!("\"hunter2\"", json);
+
+        let deserialized: SecretString = serde_json::from_str(&json).unwrap();
+        assert_eq!(password, deserialized);
+    }
+
+    /// test function for the serialization of secret keys
+    #[test]
+    fn assert_secret_key_serialization() {
+        let key = SecretKey::random();
+
+        let json = serde_json::to_string(&key).unwrap();
+        let deserialized: SecretKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(key, deserialized);
+
+        assert!(serde_json::from_str::<SecretKey>("\"c2hvcnQ=\"").is_err());
+        assert!(serde_json::from_str::<SecretKey>("\"not base64\"").is_err());
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use crossterm::terminal;
+use inquire::{autocompletion::Replacement, Autocomplete, CustomUserError, InquireError};
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::{
+    cli::Command,
+    console_utils::ConsoleIO,
+    data_store::{DataStore, Dirty},
+};
+
+/// Maximum number of command lines kept in the history of the shell.
+const HISTORY_SIZE: usize = 100;
+
+/// Sub-commands of rpass which are not operated on an unlocked DataStore, hidden in the shell.
+const HIDDEN_COMMANDS: [&str; 7] = [
+    "init",
+    "change-master",
+    "backups",
+    "agent",
+    "lock",
+    "shell",
+    "kdf",
+];
+
+/// Period at which the inactivity of the shell is checked.
+const WATCHDOG_PERIOD: Duration = Duration::from_millis(500);
+
+/// Possible errors in the interactive shell.
+#[derive(Debug, Error)]
+pub enum ShellError {
+    #[error("Unterminated quote or escape in command line")]
+    InvalidQuoting,
+    #[error("This command is not available in the shell, run it with rpass directly")]
+    UnsupportedCommand,
+    #[error("Datastore has already been locked")]
+    Locked,
+}
+
+/// A command line read by the shell.
+#[derive(Parser)]
+#[command(name = "rpass>", no_binary_name = true, disable_version_flag = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: ShellCommand,
+}
+
+/// Possible commands in the shell: the rpass sub-commands, and the shell own commands.
+#[derive(Subcommand)]
+enum ShellCommand {
+    #[command(flatten)]
+    Run(Command),
+    /// Saves the pending changes of the DataStore
+    Save,
+    /// Saves the pending changes, locks the DataStore and leaves the shell
+    #[command(visible_alias = "quit")]
+    Exit,
+}
+
+impl ShellLine {
+    /// Builds the parser of the shell command lines, without the hidden commands.
+    fn parser() -> clap::Command {
+        HIDDEN_COMMANDS
+            .iter()
+            .fold(ShellLine::command(), |parser, hidden| {
+                parser.mut_subcommand(hidden, |command| command.hide(true))
+            })
+    }
+
+    /// Parses a command line split into words.
+    ///
+    /// This will return an error if the command line is invalid, or if help is requested.
+    fn parse(words: Vec<String>) -> clap::error::Result<ShellCommand> {
+        let matches = ShellLine::parser().try_get_matches_from(words)?;
+
+        Ok(ShellLine::from_arg_matches(&matches)?.command)
+    }
+}
+
+/// An unlocked DataStore, along its pending changes.
+struct Session {
+    store: DataStore<Dirty>,
+    changed: bool,
+}
+
+impl Session {
+    /// Saves the pending changes of the DataStore.
+    ///
+    /// This will return an error if the DataStore cannot be saved.
+    fn save(&mut self) -> Result<()> {
+        self.store.flush()?;
+        self.changed = false;
+
+        Ok(())
+    }
+
+    /// Locks the DataStore, which is only written back once modified (or outdated).
+    ///
+    /// This will return an error if the DataStore cannot be saved.
+    fn end(self) -> Result<DataStore> {
+        if self.changed || self.store.is_outdated() {
+            return self.store.lock();
+        }
+
+        Ok(self.store.close())
+    }
+}
+
+/// Runs the interactive shell on an unlocked DataStore, until the user leaves it.
+///
+/// Each command line is run by `execute` against the DataStore, which returns either
+/// the DataStore has been modified. Changes are kept pending until they are saved
+/// with `save`, or until the DataStore is locked upon exit.
+///
+/// Once the shell waits for a command for more than `timeout`, the DataStore is saved
+/// and locked, and the process exits.
+///
+/// This will return an error if :
+/// - the command lines cannot be read.
+/// - the DataStore cannot be saved.
+pub fn run(
+    modified: DataStore<Dirty>,
+    timeout: Duration,
+    execute: impl FnMut(&mut DataStore<Dirty>, Command) -> Result<bool>,
+) -> Result<DataStore> {
+    let console = ConsoleIO::new();
+
+    console.info(&format!(
+        "Shell opened on {}, type `help` to list the commands and `exit` to leave, the datastore is locked after {}s of inactivity",
+        modified.path().display(),
+        timeout.as_secs()
+    ));
+
+    let session = Arc::new(Mutex::new(Some(Session {
+        store: modified,
+        changed: false,
+    })));
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+
+    spawn_watchdog(session.clone(), last_activity.clone(), timeout);
+
+    let result = read_commands(&session, &last_activity, execute);
+
+    let Some(session) = session.lock().unwrap().take() else {
+        bail!(ShellError::Locked);
+    };
+
+    let data_store = session.end()?;
+    result?;
+
+    console.success("Datastore locked !");
+
+    Ok(data_store)
+}
+
+/// Reads and runs command lines until the user leaves the shell.
+///
+/// Failing commands are reported, and do not end the shell.
+///
+/// This will return an error if the command lines cannot be read.
+fn read_commands(
+    session: &Mutex<Option<Session>>,
+    last_activity: &Mutex<Instant>,
+    mut execute: impl FnMut(&mut DataStore<Dirty>, Command) -> Result<bool>,
+) -> Result<()> {
+    let console = ConsoleIO::new();
+    let mut completer = ShellCompleter::new();
+
+    loop {
+        if let Some(current) = session.lock().unwrap().as_ref() {
+            completer.labels = current.store.data().keys().cloned().sorted().collect();
+        }
+
+        let line = console
+            .input_text("rpass>")
+            .with_autocomplete(completer.clone())
+            .prompt();
+
+        *last_activity.lock().unwrap() = Instant::now();
+
+        let line = match line {
+            Ok(line) => line,
+            Err(InquireError::OperationCanceled) => continue,
+            Err(InquireError::OperationInterrupted) => return Ok(()),
+            Err(error) => bail!(error),
+        };
+
+        let words = match split_command_line(&line) {
+            Ok(words) if words.is_empty() => continue,
+            Ok(words) => words,
+            Err(error) => {
+                console.error(&format!("{error}"));
+                continue;
+            }
+        };
+
+        completer.remember(line.trim());
+
+        let command = match ShellLine::parse(words) {
+            Ok(command) => command,
+            Err(error) => {
+                // help and usage errors are formatted by clap
+                let _ = error.print();
+                continue;
+            }
+        };
+
+        let mut guard = session.lock().unwrap();
+        let Some(current) = guard.as_mut() else {
+            bail!(ShellError::Locked);
+        };
+
+        let result = match command {
+            ShellCommand::Exit => return Ok(()),
+            ShellCommand::Save => current.save().map(|_| console.success("Datastore saved !")),
+            ShellCommand::Run(command) => {
+                execute(&mut current.store, command).map(|changed| current.changed |= changed)
+            }
+        };
+
+        drop(guard);
+
+        // the time spent in a command (e.g. answering its prompts) is not inactivity
+        *last_activity.lock().unwrap() = Instant::now();
+
+        if let Err(error) = result {
+            console.error(&format!("{error}"));
+        }
+    }
+}
+
+/// Spawns a thread locking the DataStore once the shell is inactive for `timeout`.
+///
+/// The DataStore is only locked while the shell waits for a command, then the
+/// terminal is restored and the process exits.
+fn spawn_watchdog(
+    session: Arc<Mutex<Option<Session>>>,
+    last_activity: Arc<Mutex<Instant>>,
+    timeout: Duration,
+) {
+    thread::spawn(move || loop {
+        thread::sleep(WATCHDOG_PERIOD);
+
+        if last_activity.lock().unwrap().elapsed() < timeout {
+            continue;
+        }
+
+        // a running command holds the session
+        let Ok(mut guard) = session.try_lock() else {
+            continue;
+        };
+
+        let Some(current) = guard.take() else {
+            return;
+        };
+
+        let result = current.end();
+
+        // the prompt waiting for a command is left in raw mode
+        let _ = terminal::disable_raw_mode();
+
+        let console = ConsoleIO::new();
+        console.new_line();
+
+        match result {
+            Ok(_) => {
+                console.info("Datastore locked after inactivity");
+                process::exit(0);
+            }
+            Err(error) => {
+                console.error(&format!("{error}"));
+                process::exit(1);
+            }
+        }
+    });
+}
+
+/// Tab completion of the shell commands and entry labels, along the command history.
+#[derive(Clone)]
+struct ShellCompleter {
+    /// Names (and aliases) of the shell commands.
+    commands: Vec<String>,
+    /// Names of the commands taking an entry label as argument.
+    label_commands: Vec<String>,
+    /// Labels of the DataStore entries.
+    labels: Vec<String>,
+    /// Previous command lines, the most recent last.
+    history: Vec<String>,
+}
+
+impl ShellCompleter {
+    /// Creates a completer for the shell commands, without any label nor history.
+    fn new() -> Self {
+        let parser = ShellLine::parser();
+        let visible_commands = || {
+            parser
+                .get_subcommands()
+                .filter(|command| !command.is_hide_set())
+        };
+
+        let commands = visible_commands()
+            .flat_map(|command| {
+                std::iter::once(command.get_name()).chain(command.get_visible_aliases())
+            })
+            .map(String::from)
+            .collect();
+
+        let label_commands = visible_commands()
+            .filter(|command| command.get_arguments().any(|arg| arg.get_id() == "name"))
+            .map(|command| command.get_name().to_string())
+            .collect();
+
+        Self {
+            commands,
+            label_commands,
+            labels: vec![],
+            history: vec![],
+        }
+    }
+
+    /// Adds a command line to the history, moving it up if already known.
+    fn remember(&mut self, line: &str) {
+        self.history.retain(|previous| previous != line);
+        self.history.push(line.to_string());
+
+        if self.history.len() > HISTORY_SIZE {
+            self.history.remove(0);
+        }
+    }
+
+    /// Lists the completions of a partial command line, then the matching history.
+    ///
+    /// Only the history is listed for an empty command line.
+    fn suggestions(&self, input: &str) -> Vec<String> {
+        let mut suggestions: Vec<String> = match input.split_once(char::is_whitespace) {
+            _ if input.is_empty() => vec![],
+            None => self
+                .commands
+                .iter()
+                .filter(|command| command.starts_with(input) && *command != input)
+                .map(|command| match self.label_commands.contains(command) {
+                    true => format!("{command} "),
+                    false => command.clone(),
+                })
+                .collect(),
+            Some((command, argument)) if self.label_commands.iter().any(|c| c == command) => {
+                let argument = argument.trim_start().trim_start_matches(['"', '\'']);
+                let argument = argument.to_lowercase();
+
+                self.labels
+                    .iter()
+                    .filter(|label| label.to_lowercase().starts_with(&argument))
+                    .map(|label| format!("{command} {}", quote(label)))
+                    .collect()
+            }
+            Some(_) => vec![],
+        };
+
+        for previous in self.history.iter().rev() {
+            if previous.starts_with(input) && previous != input && !suggestions.contains(previous) {
+                suggestions.push(previous.clone());
+            }
+        }
+
+        suggestions
+    }
+}
+
+impl Autocomplete for ShellCompleter {
+    fn get_suggestions(&mut self, input: &str) -> Result<Vec<String>, CustomUserError> {
+        Ok(self.suggestions(input))
+    }
+
+    fn get_completion(
+        &mut self,
+        input: &str,
+        highlighted_suggestion: Option<String>,
+    ) -> Result<Replacement, CustomUserError> {
+        if highlighted_suggestion.is_some() {
+            return Ok(highlighted_suggestion);
+        }
+
+        // the input is completed up to the common prefix of its completions
+        let common_prefix = self
+            .suggestions(input)
+            .into_iter()
+            .filter(|suggestion| !self.history.contains(suggestion))
+            .reduce(|prefix, suggestion| {
+                prefix
+                    .chars()
+                    .zip(suggestion.chars())
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a)
+                    .collect()
+            });
+
+        Ok(common_prefix.filter(|prefix| prefix.chars().count() > input.chars().count()))
+    }
+}
+
+/// Splits a command line into words, like a (very) simple shell.
+///
+/// Words are separated by whitespaces, unless quoted with `'` or `"`, and any
+/// character can be escaped with `\` (except within single quotes).
+///
+/// This will return an error if a quote or an escape is not terminated.
+fn split_command_line(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                let Some(escaped) = chars.next() else {
+                    bail!(ShellError::InvalidQuoting);
+                };
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        bail!(ShellError::InvalidQuoting);
+    }
+
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Quotes a word for a command line, if needed, so that it is split back as is.
+fn quote(word: &str) -> String {
+    let is_plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'));
+
+    if is_plain {
+        return word.to_string();
+    }
+
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the command line splitting
+    #[test]
+    fn assert_command_line_is_split() {
+        assert_eq!(
+            vec!["dump", "my bank", "it's", ""],
+            split_command_line(r#"  dump "my bank"  it\'s '' "#).unwrap()
+        );
+        assert!(split_command_line("dump \"my bank").is_err());
+        assert!(split_command_line("dump bank\\").is_err());
+
+        for label in ["github", "my bank", "say \"hi\"", "back\\slash", ""] {
+            let line = format!("dump {}", quote(label));
+            assert_eq!(vec!["dump", label], split_command_line(&line).unwrap());
+        }
+    }
+
+    /// test function for the parsing of shell commands
+    #[test]
+    fn assert_shell_commands_are_parsed() {
+        let parse = |line: &str| ShellLine::parse(split_command_line(line).unwrap());
+
+        assert!(matches!(
+            parse("dump \"my bank\"").unwrap(),
+            ShellCommand::Run(Command::Dump { name, field: None }) if name.as_deref() == Some("my bank")
+        ));
+        assert!(matches!(parse("quit").unwrap(), ShellCommand::Exit));
+        assert!(parse("history").is_err());
+        assert!(parse("unknown").is_err());
+    }
+
+    /// test function for the completion of commands and labels
+    #[test]
+    fn assert_command_line_is_completed() {
+        let mut completer = ShellCompleter::new();
+        completer.labels = vec!["GitHub".into(), "Gitea".into(), "my bank".into()];
+        completer.remember("list");
+
+        assert_eq!(vec!["list"], completer.suggestions(""));
+        assert_eq!(vec!["dump "], completer.suggestions("du"));
+        assert!(completer.suggestions("in").is_empty());
+        assert_eq!(
+            vec!["dump GitHub", "dump Gitea"],
+            completer.suggestions("dump gi")
+        );
+        assert_eq!(
+            Some("dump \"my bank\"".to_string()),
+            completer.get_completion("dump m", None).unwrap()
+        );
+        assert_eq!(
+            Some("dump Git".to_string()),
+            completer.get_completion("dump g", None).unwrap()
+        );
+        assert!(completer.suggestions("list ").is_empty());
+    }
+}
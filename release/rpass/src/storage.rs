@@ -0,0 +1,465 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{constants::RPASS_BACKUP_COUNT, crypto};
+
+/// Possible errors upon file storage operations.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Invalid file path {0}")]
+    InvalidPath(PathBuf),
+    #[error("Backup {0} not found")]
+    BackupNotFound(usize),
+}
+
+/// Representation of an encrypted backup of a file.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// Version of the backup, 1 being the most recent one.
+    pub version: usize,
+    /// Path of the backup file.
+    pub path: PathBuf,
+    /// Size of the backup file, in bytes.
+    pub size: u64,
+    /// Last modification date of the backup file.
+    pub modification_date: DateTime<Utc>,
+}
+
+/// Advisory lock file held next to a file, while it is being operated on.
+///
+/// The lock itself is an exclusive lock of the operating system on the lock file,
+/// released as soon as the holding process exits, even if it crashes.
+/// The lock file contains the PID of the process holding it, and is removed
+/// when the `LockFile` is dropped.
+#[derive(Debug)]
+pub struct LockFile {
+    /// Path of the lock file.
+    path: PathBuf,
+    /// Opened lock file, holding the lock until closed.
+    file: File,
+}
+
+impl LockFile {
+    /// returns the path of the lock file of a given file.
+    pub fn path_for(path: &Path) -> Result<PathBuf> {
+        suffixed_path(path, "lock")
+    }
+
+    /// Tries to acquire the lock of a given file, without waiting.
+    ///
+    /// A lock file left by a crashed process is not locked anymore, and is reused.
+    ///
+    /// Returns `None` if the lock is held by another running process.
+    ///
+    /// This will return an error if the lock file cannot be created or locked.
+    pub fn try_acquire(path: &Path) -> Result<Option<LockFile>> {
+        let lock_path = Self::path_for(path)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(false);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        loop {
+            let mut file = options.open(&lock_path)?;
+
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(fs::TryLockError::WouldBlock) => return Ok(None),
+                Err(fs::TryLockError::Error(error)) => bail!(error),
+            }
+
+            // the previous holder removes the lock file before releasing it,
+            // so the locked file may not be the lock file anymore
+            if !is_same_file(&file, &lock_path)? {
+                continue;
+            }
+
+            file.set_len(0)?;
+            file.write_all(std::process::id().to_string().as_bytes())?;
+            file.sync_all()?;
+
+            return Ok(Some(LockFile {
+                path: lock_path,
+                file,
+            }));
+        }
+    }
+
+    /// returns the PID of the process holding the lock of a given file, if any.
+    pub fn holder(path: &Path) -> Result<Option<u32>> {
+        read_lock_pid(&Self::path_for(path)?)
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // the lock file is removed while still locked, then released when closed
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Reads the PID written in a lock file.
+///
+/// returns `None` if the lock file does not exist or does not contain a PID.
+fn read_lock_pid(lock_path: &Path) -> Result<Option<u32>> {
+    match fs::read_to_string(lock_path) {
+        Ok(content) => Ok(content.trim().parse::<u32>().ok()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => bail!(error),
+    }
+}
+
+/// Checks whether an opened file is still the one found at a given path.
+///
+/// returns `false` if the path does not exist anymore.
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let opened = file.metadata()?;
+
+    match fs::metadata(path) {
+        Ok(current) => Ok(opened.dev() == current.dev() && opened.ino() == current.ino()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(false),
+        Err(error) => bail!(error),
+    }
+}
+
+/// Checks whether an opened file is still the one found at a given path.
+///
+/// Opened files cannot be removed on other platforms, so only its existence is checked.
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> Result<bool> {
+    Ok(path.try_exists()?)
+}
+
+/// Writes a file in a crash-safe way, keeping its previous versions as backups.
+///
+/// - The content is written to a temporary file next to the target, then flushed to disk.
+///   Its name is unique, so that concurrent writers never write to the same temporary file.
+/// - The current version of the file is kept aside, then the temporary file is
+///   atomically renamed over the target.
+/// - Only once renamed, the previous versions of the file are rotated as `<file>.bak.N`.
+///
+/// The target is therefore either the old or the new version, never a partial one,
+/// and the backups are left untouched by a failed write.
+///
+/// This will return an error if any filesystem operation fails.
+pub fn atomic_write(path: &Path, content: &[u8]) -> Result<()> {
+    let temp_path = unique_path(path, "tmp")?;
+
+    let written = create_private_file(&temp_path).and_then(|mut temp_file| {
+        temp_file.write_all(content)?;
+        temp_file.sync_all()?;
+        Ok(())
+    });
+
+    let previous = written.and_then(|_| keep_aside(path));
+
+    let previous = match previous {
+        Ok(previous) => previous,
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            bail!(error);
+        }
+    };
+
+    if let Err(error) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+
+        if let Some(previous) = previous {
+            let _ = fs::remove_file(previous);
+        }
+
+        bail!(error);
+    }
+
+    if let Some(previous) = previous {
+        rotate_backups(path, &previous)?;
+    }
+
+    sync_parent_folder(path)?;
+
+    Ok(())
+}
+
+/// returns the path of a given backup version of a file.
+pub fn backup_path(path: &Path, version: usize) -> Result<PathBuf> {
+    suffixed_path(path, &format!("bak.{version}"))
+}
+
+/// Lists the existing backups of a file, from the most recent to the oldest one.
+///
+/// This will return an error if the backup files metadata cannot be read.
+pub fn list_backups(path: &Path) -> Result<Vec<Backup>> {
+    let mut backups = vec![];
+
+    for version in 1..=RPASS_BACKUP_COUNT {
+        let backup_path = backup_path(path, version)?;
+
+        if !backup_path.try_exists()? {
+            continue;
+        }
+
+        let metadata = fs::metadata(&backup_path)?;
+
+        backups.push(Backup {
+            version,
+            path: backup_path,
+            size: metadata.len(),
+            modification_date: DateTime::from(metadata.modified()?),
+        });
+    }
+
+    Ok(backups)
+}
+
+/// Restores a given backup version over a file.
+///
+/// The restoration goes through `atomic_write`, so the replaced content becomes
+/// the most recent backup and the restoration can be undone.
+///
+/// This will return an error if :
+/// - the backup cannot be found.
+/// - the backup cannot be read.
+/// - the file cannot be written.
+pub fn restore_backup(path: &Path, version: usize) -> Result<()> {
+    let backup_path = backup_path(path, version)?;
+
+    if !backup_path.try_exists()? {
+        bail!(StorageError::BackupNotFound(version));
+    }
+
+    let content = fs::read(backup_path)?;
+
+    atomic_write(path, &content)
+}
+
+/// Keeps the current version of a file aside, under a unique name, to back it up
+/// once replaced.
+///
+/// Returns `None` if the file does not exist or is empty, as empty files are not backed up.
+fn keep_aside(path: &Path) -> Result<Option<PathBuf>> {
+    if !path.try_exists()? || fs::metadata(path)?.len() == 0 {
+        return Ok(None);
+    }
+
+    let previous = unique_path(path, "prev")?;
+
+    // a hard link keeps the current file in place until the final rename
+    if fs::hard_link(path, &previous).is_err() {
+        fs::copy(path, &previous)?;
+    }
+
+    Ok(Some(previous))
+}
+
+/// Shifts the backups of a file by one version, the previous version of the file
+/// (see `keep_aside()`) becoming the most recent backup.
+///
+/// The oldest backup is dropped when `RPASS_BACKUP_COUNT` is reached.
+fn rotate_backups(path: &Path, previous: &Path) -> Result<()> {
+    for version in (1..RPASS_BACKUP_COUNT).rev() {
+        let older = backup_path(path, version)?;
+
+        if older.try_exists()? {
+            fs::rename(&older, backup_path(path, version + 1)?)?;
+        }
+    }
+
+    fs::rename(previous, backup_path(path, 1)?)?;
+
+    Ok(())
+}
+
+/// returns a path next to a file, unique to this process and this call
+/// (e.g. `file.ext.tmp.1234.5f3a9c0e7b21d4a8`).
+fn unique_path(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let random = u64::from_le_bytes(crypto::random_bytes());
+
+    suffixed_path(
+        path,
+        &format!("{suffix}.{}.{random:016x}", std::process::id()),
+    )
+}
+
+/// Creates a new file only readable by the current user, failing if it already exists.
+fn create_private_file(path: &Path) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    Ok(options.open(path)?)
+}
+
+/// Flushes the parent folder of a file, so that a rename is persisted on disk.
+fn sync_parent_folder(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        File::open(parent)?.sync_all()?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(())
+}
+
+/// returns the path of a file with an additional extension (e.g. `file.ext.suffix`).
+pub fn suffixed_path(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let Some(file_name) = path.file_name() else {
+        bail!(StorageError::InvalidPath(path.to_path_buf()));
+    };
+
+    let mut suffixed = file_name.to_os_string();
+    suffixed.push(format!(".{suffix}"));
+
+    Ok(path.with_file_name(suffixed))
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::test_utils::TempFolder;
+
+    /// test function for backup rotation upon writes
+    #[test]
+    fn assert_backups_are_rotated() {
+        let folder = TempFolder::new("storage-rotation");
+        let path = folder.join(".datastore");
+
+        for i in 0..RPASS_BACKUP_COUNT + 3 {
+            atomic_write(&path, i.to_string().as_bytes()).unwrap();
+        }
+
+        let last = RPASS_BACKUP_COUNT + 2;
+
+        assert_eq!(last.to_string(), fs::read_to_string(&path).unwrap());
+        assert_eq!(RPASS_BACKUP_COUNT, list_backups(&path).unwrap().len());
+        assert_eq!(
+            (last - 1).to_string(),
+            fs::read_to_string(backup_path(&path, 1).unwrap()).unwrap()
+        );
+        // no temporary file is left behind
+        assert_eq!(
+            RPASS_BACKUP_COUNT + 1,
+            fs::read_dir(&folder).unwrap().count()
+        );
+    }
+
+    /// test function for failed writes, leaving the backups untouched
+    #[test]
+    fn assert_failed_write_keeps_backups() {
+        let folder = TempFolder::new("storage-failure");
+        let path = folder.join(".datastore");
+
+        atomic_write(&path, b"first").unwrap();
+        atomic_write(&path, b"second").unwrap();
+
+        // a non-empty folder cannot be replaced by a file
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("content"), b"").unwrap();
+
+        assert!(atomic_write(&path, b"third").is_err());
+        assert_eq!(
+            "first",
+            fs::read_to_string(backup_path(&path, 1).unwrap()).unwrap()
+        );
+        assert_eq!(1, list_backups(&path).unwrap().len());
+        assert_eq!(2, fs::read_dir(&folder).unwrap().count());
+    }
+
+    /// test function for backup restoration
+    #[test]
+    fn assert_backup_restore() {
+        let folder = TempFolder::new("storage-restore");
+        let path = folder.join(".datastore");
+
+        atomic_write(&path, b"first").unwrap();
+        atomic_write(&path, b"second").unwrap();
+        atomic_write(&path, b"third").unwrap();
+
+        restore_backup(&path, 2).unwrap();
+
+        assert_eq!("first", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            "third",
+            fs::read_to_string(backup_path(&path, 1).unwrap()).unwrap()
+        );
+        assert!(restore_backup(&path, RPASS_BACKUP_COUNT + 1).is_err());
+    }
+
+    /// test function for lock files
+    #[test]
+    fn assert_lock_file() {
+        let folder = TempFolder::new("storage-lock");
+        let path = folder.join(".datastore");
+        let lock_path = LockFile::path_for(&path).unwrap();
+
+        let lock = LockFile::try_acquire(&path).unwrap();
+
+        assert!(lock.is_some());
+        assert_eq!(Some(std::process::id()), LockFile::holder(&path).unwrap());
+        assert!(LockFile::try_acquire(&path).unwrap().is_none());
+
+        drop(lock);
+
+        assert!(!lock_path.exists());
+
+        // lock file left by a process which is not running anymore
+        fs::write(&lock_path, u32::MAX.to_string()).unwrap();
+
+        let lock = LockFile::try_acquire(&path).unwrap();
+
+        assert!(lock.is_some());
+        assert_eq!(Some(std::process::id()), LockFile::holder(&path).unwrap());
+
+        drop(lock);
+
+        assert!(!lock_path.exists());
+
+        // concurrent attempts never hold the lock together
+        let holders = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        if let Some(lock) = LockFile::try_acquire(&path).unwrap() {
+                            assert_eq!(0, holders.fetch_add(1, Ordering::SeqCst));
+                            holders.fetch_sub(1, Ordering::SeqCst);
+                            drop(lock);
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
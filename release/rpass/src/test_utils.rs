@@ -0,0 +1,52 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of temporary folders created by the tests of the current process.
+static FOLDER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty temporary folder for tests, removed along its content when dropped.
+pub struct TempFolder {
+    /// Path of the folder.
+    path: PathBuf,
+}
+
+impl TempFolder {
+    /// Creates an empty temporary folder, whose name is unique among the tests of
+    /// the current process, and prefixed by a given name to spot it if left behind.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "rpass-{name}-{}-{}",
+            std::process::id(),
+            FOLDER_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+}
+
+impl Deref for TempFolder {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempFolder {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFolder {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,499 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use itertools::Itertools;
+use zeroize::Zeroizing;
+
+use super::BrowserError;
+use crate::{
+    data_store::{DataStore, Dirty, PasswordStore},
+    entry::EntryKind,
+    middleware::HandlingError,
+    passwords,
+};
+
+/// Length of the passwords generated in the entry form.
+const GENERATED_PASSWORD_LENGTH: usize = 24;
+
+/// Index of the label field in the entry form.
+const LABEL_FIELD: usize = 0;
+
+/// Index of the password field in the entry form.
+const PASSWORD_FIELD: usize = 3;
+
+/// State of the browser, operated on a DataStore with keystrokes.
+pub struct App<'a> {
+    /// The unlocked DataStore being browsed.
+    store: &'a mut DataStore<Dirty>,
+    /// Whether the DataStore has been modified.
+    changed: bool,
+    /// Whether the browser must be left.
+    quit: bool,
+    /// Search query filtering the entries.
+    query: String,
+    /// Labels of the entries matching the query, sorted.
+    labels: Vec<String>,
+    /// Index of the selected entry in `labels`.
+    selected: usize,
+    /// Whether the password of the selected entry is revealed.
+    reveal: bool,
+    /// Current interaction mode.
+    mode: Mode,
+    /// Message about the last action, if any.
+    status: Option<Status>,
+}
+
+/// Interaction modes of the browser.
+pub enum Mode {
+    /// Moving through the entries.
+    Browse,
+    /// Typing the search query.
+    Search,
+    /// Filling the form of a new or edited entry.
+    Form(Form),
+    /// Waiting for the confirmation of the deletion of an entry.
+    ConfirmDelete(String),
+}
+
+/// Message about the last action.
+pub struct Status {
+    pub message: String,
+    pub is_error: bool,
+}
+
+/// Form of a new or edited entry.
+pub struct Form {
+    /// Whether an existing entry is edited, rather than a new one added.
+    pub editing: bool,
+    pub fields: Vec<FormField>,
+    /// Index of the focused field.
+    pub focus: usize,
+    /// Whether the storage of a weak password has been asked to be confirmed.
+    confirm_weak: bool,
+}
+
+/// Field of the entry form.
+pub struct FormField {
+    pub name: &'static str,
+    pub value: Zeroizing<String>,
+    /// Whether the value is masked.
+    pub secret: bool,
+    pub read_only: bool,
+}
+
+impl FormField {
+    /// creates a field with an initial value.
+    fn new(name: &'static str, value: &str) -> Self {
+        Self {
+            name,
+            value: Zeroizing::new(value.to_string()),
+            secret: false,
+            read_only: false,
+        }
+    }
+}
+
+impl Form {
+    /// creates an empty form to add an entry.
+    fn add() -> Self {
+        Self::with_values(false, ["", "", "", "", ""])
+    }
+
+    /// creates a form prefilled with the values of an entry to edit, whose label is read-only.
+    fn edit(entry: &PasswordStore) -> Self {
+        let mut form = Self::with_values(
+            true,
+            [
+                &entry.label,
+                entry.url.as_deref().unwrap_or_default(),
+                entry.login.as_deref().unwrap_or_default(),
+                entry.password.expose(),
+                entry.comment.as_deref().unwrap_or_default(),
+            ],
+        );
+
+        form.fields[LABEL_FIELD].read_only = true;
+        form.focus = 1;
+
+        form
+    }
+
+    /// creates a form given the values of its fields.
+    fn with_values(editing: bool, [label, url, login, password, comment]: [&str; 5]) -> Self {
+        let mut password = FormField::new("Password", password);
+        password.secret = true;
+
+        Self {
+            editing,
+            fields: vec![
+                FormField::new("Label", label),
+                FormField::new("URL", url),
+                FormField::new("Login", login),
+                password,
+                FormField::new("Comment", comment),
+            ],
+            focus: LABEL_FIELD,
+            confirm_weak: false,
+        }
+    }
+
+    /// returns the value of a field, or `None` if empty.
+    fn optional_value(&self, index: usize) -> Option<String> {
+        let value = self.fields[index].value.trim();
+
+        (!value.is_empty()).then(|| value.to_string())
+    }
+
+    /// moves the focus to the next (or previous) editable field.
+    fn move_focus(&mut self, forward: bool) {
+        let count = self.fields.len();
+
+        for _ in 0..count {
+            self.focus = match forward {
+                true => (self.focus + 1) % count,
+                false => (self.focus + count - 1) % count,
+            };
+
+            if !self.fields[self.focus].read_only {
+                return;
+            }
+        }
+    }
+
+    /// returns the password typed in the form.
+    pub fn password(&self) -> &str {
+        &self.fields[PASSWORD_FIELD].value
+    }
+}
+
+impl<'a> App<'a> {
+    /// creates a browser on an unlocked DataStore.
+    pub fn new(store: &'a mut DataStore<Dirty>) -> Self {
+        let mut app = Self {
+            store,
+            changed: false,
+            quit: false,
+            query: String::new(),
+            labels: vec![],
+            selected: 0,
+            reveal: false,
+            mode: Mode::Browse,
+            status: None,
+        };
+
+        app.filter();
+
+        app
+    }
+
+    /// returns whether the DataStore has been modified.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// returns whether the browser must be left.
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// returns the search query.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// returns the labels of the entries matching the search query.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// returns the index of the selected entry.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// returns the selected entry, if any.
+    pub fn selected(&self) -> Option<&PasswordStore> {
+        let label = self.labels.get(self.selected)?;
+
+        self.store.get(label).ok()
+    }
+
+    /// returns whether the password of the selected entry is revealed.
+    pub fn is_revealed(&self) -> bool {
+        self.reveal
+    }
+
+    /// returns the current interaction mode.
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// returns the message about the last action, if any.
+    pub fn status(&self) -> Option<&Status> {
+        self.status.as_ref()
+    }
+
+    /// returns the path of the DataStore file.
+    pub fn path(&self) -> &std::path::Path {
+        self.store.path()
+    }
+
+    /// Handles a keystroke, depending on the current interaction mode.
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return;
+        }
+
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => self.handle_browse_key(key),
+            Mode::Search => self.handle_search_key(key),
+            Mode::Form(form) => self.handle_form_key(form, key),
+            Mode::ConfirmDelete(label) => self.handle_delete_key(label, key),
+        }
+    }
+
+    /// Handles a keystroke while moving through the entries.
+    fn handle_browse_key(&mut self, key: KeyEvent) {
+        self.status = None;
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Char('r') => self.reveal = !self.reveal,
+            KeyCode::Char('a') => self.mode = Mode::Form(Form::add()),
+            KeyCode::Char('e') | KeyCode::Enter => match self.selected() {
+                // the form only holds the fields of logins
+                Some(entry) if entry.kind != EntryKind::Login => {
+                    let message = format!(
+                        "{} entries cannot be edited here, please run `rpass edit`",
+                        entry.kind
+                    );
+                    self.error(&message);
+                }
+                Some(entry) => self.mode = Mode::Form(Form::edit(entry)),
+                None => {}
+            },
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(label) = self.labels.get(self.selected) {
+                    self.mode = Mode::ConfirmDelete(label.clone());
+                }
+            }
+            KeyCode::Esc if !self.query.is_empty() => {
+                self.query.clear();
+                self.filter();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            _ => {}
+        }
+    }
+
+    /// Handles a keystroke while typing the search query.
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => return,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.filter();
+                return;
+            }
+            KeyCode::Up => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down => self.select(self.selected + 1),
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.filter();
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.filter();
+            }
+            _ => {}
+        }
+
+        self.mode = Mode::Search;
+    }
+
+    /// Handles a keystroke while filling the entry form.
+    fn handle_form_key(&mut self, mut form: Form, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.info("Changes discarded");
+                return;
+            }
+            KeyCode::Enter => match self.submit(&mut form) {
+                Ok(message) => {
+                    self.info(&message);
+                    return;
+                }
+                Err(error) => self.error(&error.to_string()),
+            },
+            KeyCode::Tab | KeyCode::Down => form.move_focus(true),
+            KeyCode::BackTab | KeyCode::Up => form.move_focus(false),
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                match passwords::generate(GENERATED_PASSWORD_LENGTH) {
+                    Ok(generated) => {
+                        form.fields[PASSWORD_FIELD].value = Zeroizing::new(generated);
+                        form.confirm_weak = false;
+                    }
+                    Err(error) => self.error(&error.to_string()),
+                }
+            }
+            KeyCode::Backspace if !form.fields[form.focus].read_only => {
+                form.fields[form.focus].value.pop();
+                form.confirm_weak = false;
+            }
+            KeyCode::Char(c) if !form.fields[form.focus].read_only => {
+                form.fields[form.focus].value.push(c);
+                form.confirm_weak = false;
+            }
+            _ => {}
+        }
+
+        self.mode = Mode::Form(form);
+    }
+
+    /// Handles a keystroke while waiting for the confirmation of a deletion.
+    fn handle_delete_key(&mut self, label: String, key: KeyEvent) {
+        if !matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            self.info(&HandlingError::DeleteAborted.to_string());
+            return;
+        }
+
+        match self.store.delete(&label) {
+            Ok(()) => {
+                self.changed = true;
+                self.filter();
+                self.info(&format!("Entry \"{label}\" deleted !"));
+            }
+            Err(error) => self.error(&error.to_string()),
+        }
+    }
+
+    /// Records the entry of the form in the DataStore, and returns a success message.
+    ///
+    /// A new weak password must be confirmed by submitting the form a second time.
+    ///
+    /// This will return an error if :
+    /// - the label or the password is empty.
+    /// - the label of a new entry already exists in the DataStore.
+    /// - the password strength cannot be calculated.
+    /// - the weak password is not confirmed yet.
+    /// - the entry cannot be recorded in the DataStore.
+    fn submit(&mut self, form: &mut Form) -> Result<String> {
+        let Some(label) = form.optional_value(LABEL_FIELD) else {
+            bail!(BrowserError::LabelRequired);
+        };
+
+        if !form.editing && self.store.get(&label).is_ok() {
+            bail!(HandlingError::KeyAlreadyExists(label));
+        }
+
+        if form.password().is_empty() {
+            bail!(BrowserError::PasswordRequired);
+        }
+
+        // as upon edition in the CLI, only a changed password is checked
+        let is_new_password = match self.store.get(&label) {
+            Ok(current) => current.password.expose() != form.password(),
+            Err(_) => true,
+        };
+
+        if is_new_password
+            && !form.confirm_weak
+            && passwords::get_password_strength(form.password())? < 3
+        {
+            form.confirm_weak = true;
+            bail!(BrowserError::UnsafePassword);
+        }
+
+        let data = PasswordStore {
+            login: form.optional_value(2),
+            url: form.optional_value(1),
+            comment: form.optional_value(4),
+            ..PasswordStore::new(&label, form.password().into())
+        };
+
+        let message = if form.editing {
+            let current = self.store.get(&label)?.clone();
+
+            self.store.update(&PasswordStore {
+                creation_date: current.creation_date,
+                modification_date: Some(Utc::now()),
+                history: current.history,
+                otp: current.otp,
+                expiration_date: current.expiration_date,
+                kind: current.kind,
+                fields: current.fields,
+                attachments: current.attachments,
+                ..data
+            })?;
+
+            format!("Password \"{label}\" updated !")
+        } else {
+            self.store.insert(&data)?;
+
+            format!("Password \"{label}\" added !")
+        };
+
+        self.changed = true;
+        self.filter();
+        self.select(self.labels.iter().position(|l| *l == label).unwrap_or(0));
+
+        Ok(message)
+    }
+
+    /// Lists the labels of the entries matching the search query, keeping the selection in range.
+    ///
+    /// The query is fuzzy matched against labels, logins, URLs and comments, the best
+    /// matches first, and every entry is listed by label without query.
+    fn filter(&mut self) {
+        let previous = self.labels.get(self.selected).cloned();
+
+        self.labels = match self.query.is_empty() {
+            true => self.store.data().keys().cloned().sorted().collect(),
+            false => self
+                .store
+                .search(&self.query)
+                .into_iter()
+                .map(|entry| entry.label.clone())
+                .collect(),
+        };
+
+        self.select(self.selected);
+
+        // another entry may now be listed at the selected index
+        if self.labels.get(self.selected) != previous.as_ref() {
+            self.reveal = false;
+        }
+    }
+
+    /// Selects an entry given its index, within the matching entries.
+    ///
+    /// The password is masked again if another entry gets selected.
+    fn select(&mut self, index: usize) {
+        let index = index.min(self.labels.len().saturating_sub(1));
+
+        if self.labels.get(index) != self.labels.get(self.selected) {
+            self.reveal = false;
+        }
+
+        self.selected = index;
+    }
+
+    /// Reports the success of an action.
+    fn info(&mut self, message: &str) {
+        self.status = Some(Status {
+            message: message.to_string(),
+            is_error: false,
+        });
+    }
+
+    /// Reports the failure of an action.
+    fn error(&mut self, message: &str) {
+        self.status = Some(Status {
+            message: message.to_string(),
+            is_error: true,
+        });
+    }
+}
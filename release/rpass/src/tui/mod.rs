@@ -0,0 +1,228 @@
+//! Full-screen terminal browser of an unlocked DataStore.
+//!
+//! The state of the browser (`app`) is rendered with ratatui widgets (`view`), on
+//! the actual terminal, or on an in-memory screen in tests.
+
+mod app;
+mod view;
+
+use std::io::{self, BufWriter};
+
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyEventKind},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use thiserror::Error;
+
+use crate::data_store::{DataStore, Dirty};
+use app::App;
+
+/// Possible errors upon editing entries in the browser.
+#[derive(Debug, Error)]
+pub enum BrowserError {
+    #[error("Label is required")]
+    LabelRequired,
+    #[error("Password is required")]
+    PasswordRequired,
+    #[error("Your password seems to be not safe enough, press Enter again to store it as it is")]
+    UnsafePassword,
+}
+
+/// Browses an unlocked DataStore in full-screen, until the user leaves.
+///
+/// Entries added, edited or deleted are recorded in the DataStore, and
+/// returns either the DataStore has been modified.
+///
+/// This will return an error if the terminal cannot be operated.
+pub fn browse(modified: &mut DataStore<Dirty>) -> Result<bool> {
+    let mut app = App::new(modified);
+
+    let _screen = FullScreen::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(BufWriter::new(io::stdout())))?;
+
+    while !app.should_quit() {
+        terminal.draw(|frame| view::render(&app, frame))?;
+
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => app.handle_key(key),
+            _ => {}
+        }
+    }
+
+    Ok(app.is_changed())
+}
+
+/// The terminal switched to full-screen (e.g. alternate screen and raw mode), until dropped.
+struct FullScreen;
+
+impl FullScreen {
+    /// Switches the terminal to full-screen.
+    ///
+    /// This will return an error if the terminal does not support it.
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+
+        // the terminal is restored upon failure, as soon as the guard exists
+        let screen = Self;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+
+        Ok(screen)
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::PasswordStore;
+    use crate::test_utils::TempFolder;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::backend::TestBackend;
+
+    /// creates an unlocked DataStore holding the given entries, in a temporary folder.
+    fn data_store(folder: &TempFolder, labels: &[&str]) -> DataStore<Dirty> {
+        let mut modified = DataStore::new(folder.join(".datastore"))
+            .initialize("master")
+            .unwrap();
+
+        for label in labels {
+            modified
+                .insert(&PasswordStore {
+                    login: Some(format!("{label}-login")),
+                    ..PasswordStore::new(label, "correct horse battery staple".into())
+                })
+                .unwrap();
+        }
+
+        modified
+    }
+
+    /// types keys in the browser.
+    fn type_keys(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.handle_key(KeyEvent::new(*key, KeyModifiers::NONE));
+        }
+    }
+
+    /// types a text in the browser.
+    fn type_text(app: &mut App, text: &str) {
+        type_keys(app, &text.chars().map(KeyCode::Char).collect::<Vec<_>>());
+    }
+
+    /// renders the browser on an in-memory screen.
+    fn screen(app: &App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| view::render(app, frame)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|line| {
+                line.iter()
+                    .map(|cell| cell.symbol.as_str())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// test function for the rendering of entries, and the reveal of passwords
+    #[test]
+    fn assert_entries_are_rendered() {
+        let folder = TempFolder::new("tui-render");
+        let mut modified = data_store(&folder, &["github", "bank"]);
+        let mut app = App::new(&mut modified);
+
+        let rendered = screen(&app);
+        assert!(rendered.contains("Entries (2)"));
+        assert!(rendered.contains("bank-login"));
+        assert!(rendered.contains(crate::middleware::PASSWORD_MASK));
+        assert!(!rendered.contains("correct horse battery staple"));
+
+        type_keys(&mut app, &[KeyCode::Down, KeyCode::Char('r')]);
+
+        let rendered = screen(&app);
+        assert!(rendered.contains("github-login"));
+        assert!(rendered.contains("correct horse battery staple"));
+
+        // moving to another entry masks the password again
+        type_keys(&mut app, &[KeyCode::Up]);
+        assert!(!screen(&app).contains("correct horse battery staple"));
+
+        // searching lists another entry at the selected index, and masks it
+        type_keys(&mut app, &[KeyCode::Char('r'), KeyCode::Char('/')]);
+        type_text(&mut app, "git");
+        assert!(screen(&app).contains("github-login"));
+        assert!(!screen(&app).contains("correct horse battery staple"));
+
+        // deleting the revealed entry selects the next one, masked
+        type_keys(&mut app, &[KeyCode::Esc, KeyCode::Char('r')]);
+        assert!(screen(&app).contains("bank-login"));
+        assert!(screen(&app).contains("correct horse battery staple"));
+
+        type_keys(&mut app, &[KeyCode::Char('d'), KeyCode::Char('y')]);
+        let rendered = screen(&app);
+        assert!(rendered.contains("Entries (1)"));
+        assert!(!rendered.contains("correct horse battery staple"));
+    }
+
+    /// test function for the search, addition, edition and deletion of entries
+    #[test]
+    fn assert_entries_are_managed() {
+        let folder = TempFolder::new("tui-manage");
+        let mut modified = data_store(&folder, &["github", "bank"]);
+        let mut app = App::new(&mut modified);
+
+        type_keys(&mut app, &[KeyCode::Char('/')]);
+        type_text(&mut app, "GIT");
+        type_keys(&mut app, &[KeyCode::Enter]);
+        assert_eq!(["github"], app.labels());
+
+        type_keys(&mut app, &[KeyCode::Esc, KeyCode::Char('a')]);
+        type_text(&mut app, "mail");
+        type_keys(&mut app, &[KeyCode::Tab, KeyCode::Tab, KeyCode::Tab]);
+        type_text(&mut app, "weak");
+
+        // a weak password must be confirmed
+        type_keys(&mut app, &[KeyCode::Enter]);
+        assert!(screen(&app).contains("not safe enough"));
+        assert!(!app.is_changed());
+
+        type_keys(&mut app, &[KeyCode::Enter]);
+        assert!(app.is_changed());
+        assert_eq!(["bank", "github", "mail"], app.labels());
+        assert_eq!("mail", app.selected().unwrap().label);
+
+        type_keys(&mut app, &[KeyCode::Char('e'), KeyCode::Tab]);
+        type_text(&mut app, "me");
+        type_keys(&mut app, &[KeyCode::Enter]);
+        assert_eq!(Some("me".into()), app.selected().unwrap().login);
+
+        type_keys(&mut app, &[KeyCode::Char('d'), KeyCode::Char('n')]);
+        assert_eq!(3, app.labels().len());
+
+        type_keys(&mut app, &[KeyCode::Char('d'), KeyCode::Char('y')]);
+        assert_eq!(["bank", "github"], app.labels());
+
+        type_keys(&mut app, &[KeyCode::Char('q')]);
+        assert!(app.should_quit());
+        drop(app);
+
+        assert!(modified.get("mail").is_err());
+    }
+}
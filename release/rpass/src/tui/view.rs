@@ -0,0 +1,344 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use super::app::{App, Form, Mode};
+use crate::{middleware::PASSWORD_MASK, passwords};
+
+/// Minimum width of the entry list.
+const LIST_MIN_WIDTH: u16 = 24;
+
+/// Width of the field names in the detail pane and in the entry form.
+const FIELD_NAME_WIDTH: usize = 17;
+
+/// Renders a frame of the browser.
+///
+/// The screen is split in a title bar, a search bar, the entry list along the
+/// detail pane (or the entry form), and a status bar.
+pub fn render<B: Backend>(app: &App, frame: &mut Frame<B>) {
+    let area = frame.size();
+
+    if area.height < 5 || area.width < LIST_MIN_WIDTH * 2 {
+        frame.render_widget(Paragraph::new("Terminal too small"), area);
+        return;
+    }
+
+    let rows = Layout::default()
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length((area.width / 3).max(LIST_MIN_WIDTH)),
+            Constraint::Min(0),
+        ])
+        .split(rows[2]);
+
+    render_title(app, frame, rows[0]);
+    render_search(app, frame, rows[1]);
+    render_list(app, frame, columns[0]);
+
+    match app.mode() {
+        Mode::Form(form) => render_form(form, frame, columns[1]),
+        _ => render_details(app, frame, columns[1]),
+    }
+
+    render_status(app, frame, rows[3]);
+}
+
+/// Renders the title bar, with the path of the DataStore.
+fn render_title<B: Backend>(app: &App, frame: &mut Frame<B>, area: Rect) {
+    let style = Style::default().add_modifier(Modifier::REVERSED);
+    let bold = style.add_modifier(Modifier::BOLD);
+
+    let title = Spans::from(vec![
+        Span::styled(" rpass ", bold),
+        Span::raw(app.path().display().to_string()),
+    ]);
+
+    frame.render_widget(Paragraph::new(title).style(style), area);
+
+    if app.is_changed() {
+        frame.render_widget(
+            Paragraph::new(Span::styled(" ● unsaved changes ", bold)).alignment(Alignment::Right),
+            area,
+        );
+    }
+}
+
+/// Renders the search bar, highlighted while the query is typed.
+fn render_search<B: Backend>(app: &App, frame: &mut Frame<B>, area: Rect) {
+    let area = indented(area);
+    let searching = matches!(app.mode(), Mode::Search);
+
+    if !searching && app.query().is_empty() {
+        frame.render_widget(
+            Paragraph::new("Press / to search").style(Style::default().add_modifier(Modifier::DIM)),
+            area,
+        );
+        return;
+    }
+
+    let style = match searching {
+        true => Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+        false => Style::default(),
+    };
+
+    let mut search = vec![Span::styled(format!("/ {}", app.query()), style)];
+
+    if searching {
+        search.push(cursor(style));
+    }
+
+    frame.render_widget(Paragraph::new(Spans::from(search)), area);
+}
+
+/// Renders the list of the entries matching the query, scrolled to the selected one.
+fn render_list<B: Backend>(app: &App, frame: &mut Frame<B>, area: Rect) {
+    let items = app
+        .labels()
+        .iter()
+        .map(|label| ListItem::new(format!(" {label}")))
+        .collect::<Vec<_>>();
+
+    let list = List::new(items)
+        .block(boxed(
+            format!("Entries ({})", app.labels().len()),
+            Style::default(),
+        ))
+        .highlight_style(
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        );
+
+    let mut state = ListState::default();
+    state.select((!app.labels().is_empty()).then_some(app.selected_index()));
+
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Renders the fields of the selected entry, its password being masked unless revealed.
+fn render_details<B: Backend>(app: &App, frame: &mut Frame<B>, area: Rect) {
+    let block = boxed("Details".to_string(), Style::default());
+
+    let Some(entry) = app.selected() else {
+        let no_entry = Span::styled(" No entry", Style::default().add_modifier(Modifier::DIM));
+
+        frame.render_widget(Paragraph::new(no_entry).block(block), area);
+        return;
+    };
+
+    let format_date = |date: DateTime<Local>| date.format("%v %X").to_string();
+
+    let password = match app.is_revealed() {
+        true => entry.password.expose().to_string(),
+        false => PASSWORD_MASK.to_string(),
+    };
+
+    let mut fields = vec![
+        ("Label", entry.label.clone()),
+        ("Kind", entry.kind.to_string()),
+    ];
+    fields.extend(entry.kind.details());
+    fields.extend([
+        ("URL", entry.url.clone().unwrap_or_default()),
+        ("Login", entry.login.clone().unwrap_or_default()),
+        (entry.kind.secret_name(), password),
+        ("Comment", entry.comment.clone().unwrap_or_default()),
+        ("Created", format_date(entry.creation_date.into())),
+        (
+            "Modified",
+            entry
+                .modification_date
+                .map(|date| format_date(date.into()))
+                .unwrap_or_default(),
+        ),
+        (
+            "Strength",
+            match entry.kind.checks_strength() {
+                true => {
+                    passwords::format_password_strength(entry.password.expose()).unwrap_or_default()
+                }
+                false => String::new(),
+            },
+        ),
+        (
+            "History",
+            format!("{} previous password(s)", entry.history.len()),
+        ),
+        (
+            "Expires",
+            entry
+                .expiration_date
+                .map(|date| date.format("%v").to_string())
+                .unwrap_or_default(),
+        ),
+        (
+            "Two-factor",
+            entry
+                .otp
+                .as_ref()
+                .map_or("None".to_string(), |secret| secret.to_string()),
+        ),
+        (
+            "Attachments",
+            entry
+                .attachments
+                .iter()
+                .map(|attachment| attachment.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+    ]);
+
+    for field in &entry.fields {
+        let value = match field.secret && !app.is_revealed() {
+            true => PASSWORD_MASK.to_string(),
+            false => field.value.expose().to_string(),
+        };
+
+        fields.push((&field.name, value));
+    }
+
+    let lines = fields
+        .iter()
+        .map(|(name, value)| Spans::from(named_field(name, value, Style::default())))
+        .collect::<Vec<_>>();
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Renders the form of a new or edited entry, the focused field being highlighted.
+fn render_form<B: Backend>(form: &Form, frame: &mut Frame<B>, area: Rect) {
+    let title = match form.editing {
+        true => "Edit entry",
+        false => "Add entry",
+    };
+
+    let mut lines = vec![];
+
+    for (row, form_field) in form.fields.iter().enumerate() {
+        let value = match form_field.secret {
+            true => "*".repeat(form_field.value.chars().count()),
+            false => form_field.value.to_string(),
+        };
+
+        let style = match (row == form.focus, form_field.read_only) {
+            (true, _) => Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            (false, true) => Style::default().add_modifier(Modifier::DIM),
+            (false, false) => Style::default(),
+        };
+
+        let mut line = named_field(form_field.name, &value, style);
+
+        if row == form.focus {
+            line.push(cursor(style));
+        }
+
+        lines.push(Spans::from(line));
+    }
+
+    if !form.password().is_empty() {
+        let strength = passwords::format_password_strength(form.password()).unwrap_or_default();
+
+        lines.push(Spans::default());
+        lines.push(Spans::from(named_field(
+            "Strength",
+            &strength,
+            Style::default(),
+        )));
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines).block(boxed(title.to_string(), Style::default().fg(Color::Yellow))),
+        area,
+    );
+}
+
+/// returns the spans of a named field, its value being aligned with the other fields.
+fn named_field<'a>(name: &str, value: &str, style: Style) -> Vec<Span<'a>> {
+    vec![
+        Span::styled(
+            format!(" {:FIELD_NAME_WIDTH$}", format!("{name}:")),
+            style.add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(value.to_string(), style),
+    ]
+}
+
+/// Renders the status bar: the message about the last action, or the keys of the current mode.
+fn render_status<B: Backend>(app: &App, frame: &mut Frame<B>, area: Rect) {
+    let (text, style) = match (app.mode(), app.status()) {
+        (Mode::ConfirmDelete(label), _) => (
+            format!("Are you sure you want to delete entry \"{label}\" ? (y/N)"),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        (_, Some(status)) if status.is_error => (
+            status.message.clone(),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        (_, Some(status)) => (
+            status.message.clone(),
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        ),
+        (Mode::Browse, None) => (
+            "↑↓ move · / search · r reveal · a add · e edit · d delete · q quit".to_string(),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+        (Mode::Search, None) => (
+            "type to search · ↑↓ move · enter done · esc clear".to_string(),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+        (Mode::Form(_), None) => (
+            "tab next field · ctrl-g generate password · enter save · esc cancel".to_string(),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+    };
+
+    frame.render_widget(Paragraph::new(Span::styled(text, style)), indented(area));
+}
+
+/// returns a block bordering an area, with a title in its top border.
+fn boxed<'a>(title: String, style: Style) -> Block<'a> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(style)
+        .title(Span::styled(
+            format!(" {title} "),
+            style.add_modifier(Modifier::BOLD),
+        ))
+}
+
+/// returns a text cursor, e.g. a blank cell of the reversed style.
+fn cursor<'a>(style: Style) -> Span<'a> {
+    Span::styled(" ", style.add_modifier(Modifier::REVERSED))
+}
+
+/// returns an area without its first column.
+fn indented(area: Rect) -> Rect {
+    Rect {
+        x: area.x + 1,
+        width: area.width.saturating_sub(1),
+        ..area
+    }
+}
@@ -1,15 +1,20 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use anyhow::{bail, Result};
 use thiserror::Error;
 
-use crate::constants::{RPASS_DATASTORE_FILENAME, RPASS_SUBFOLDER};
+use crate::constants::{RPASS_DATASTORE_FILENAME, RPASS_SUBFOLDER, RPASS_VAULT_EXTENSION};
 
 /// Possible errors upon file storing.
 #[derive(Debug, Error)]
 pub enum StoreFileError {
     #[error("Cannot find home directory in your system.")]
     HomeDirNotFound,
+    #[error("Invalid vault name \"{0}\", only letters, digits, '-' and '_' are allowed.")]
+    InvalidVaultName(String),
 }
 
 /// returns the representation of the storage folder for the password datastore.
@@ -29,3 +34,67 @@ pub fn get_store_folder_path() -> Result<PathBuf> {
 pub fn get_store_file_path() -> Result<PathBuf> {
     Ok(get_store_folder_path()?.join(RPASS_DATASTORE_FILENAME))
 }
+
+/// returns the path of a named vault file.
+///
+/// This will return an error if the vault name contains anything else than
+/// letters, digits, '-' and '_'.
+pub fn get_vault_file_path(name: &str) -> Result<PathBuf> {
+    let is_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if !is_valid {
+        bail!(StoreFileError::InvalidVaultName(name.into()));
+    }
+
+    Ok(get_store_folder_path()?.join(format!("{name}.{RPASS_VAULT_EXTENSION}")))
+}
+
+/// returns the path of the datastore file to operate on.
+///
+/// An explicit `store` path takes precedence over a named `vault`, and the
+/// default datastore file is used when none of them is given.
+pub fn resolve_store_file_path(store: Option<&Path>, vault: Option<&str>) -> Result<PathBuf> {
+    match (store, vault) {
+        (Some(store), _) => Ok(store.to_path_buf()),
+        (None, Some(vault)) => get_vault_file_path(vault),
+        (None, None) => get_store_file_path(),
+    }
+}
+
+/// creates a command running a shell command line.
+pub fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test function for the datastore path resolution
+    #[test]
+    fn assert_store_file_path_resolution() {
+        let store = PathBuf::from("/tmp/custom.datastore");
+
+        assert_eq!(
+            store,
+            resolve_store_file_path(Some(&store), Some("work")).unwrap()
+        );
+        assert!(resolve_store_file_path(None, Some("work"))
+            .unwrap()
+            .ends_with("work.datastore"));
+        assert!(resolve_store_file_path(None, Some("../work")).is_err());
+        assert!(resolve_store_file_path(None, Some("")).is_err());
+    }
+}
@@ -0,0 +1,820 @@
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{self, KdfParams, KEY_LENGTH, NONCE_LENGTH, SALT_LENGTH, TAG_LENGTH},
+    keyfile::{Keyfile, KEYFILE_FINGERPRINT_LENGTH},
+    secret::SecretKey,
+};
+
+/// Magic number at the start of every vault file.
+pub const VAULT_MAGIC: &[u8; 8] = b"RPASSVLT";
+
+/// Current version of the vault file format.
+///
+/// Version history:
+/// - `0`: base64(JSON(`LegacyMessage`)), with hard-coded Argon2id parameters.
+/// - `1`: binary header with the Argon2id parameters, followed by the ciphertext
+///   ciphered with the key derived from the master password.
+/// - `2`: binary header with key slots (see `VaultHeader`), followed by the
+///   ciphertext ciphered with a random vault key.
+/// - `3`: same as `2`, with master password key slots which may require a keyfile.
+pub const VAULT_FORMAT_VERSION: u16 = 3;
+
+/// Maximum number of key slots of a vault file, each of them being tried upon opening.
+pub const MAX_KEY_SLOTS: u8 = 16;
+
+/// Key of a vault, ciphering its content.
+pub type VaultKey = SecretKey;
+
+/// Possible errors upon vault file parsing.
+#[derive(Debug, Error)]
+pub enum VaultFormatError {
+    #[error("Vault file is empty")]
+    Empty,
+    #[error("Vault file is truncated")]
+    Truncated,
+    #[error("Vault file format is not recognized")]
+    UnknownFormat,
+    #[error("Vault file format version {0} is not supported, please upgrade rpass")]
+    UnsupportedVersion(u16),
+    #[error("Key derivation function {0} is not supported")]
+    UnsupportedKdf(u8),
+    #[error("Key slot kind {0} is not supported")]
+    UnsupportedKeySlot(u8),
+    #[error("Vault file has too many key slots")]
+    TooManyKeySlots,
+    #[error("Key derivation parameters ({0}) exceed the supported maximum")]
+    KdfParamsTooHigh(KdfParams),
+    #[error("Wrong master password")]
+    WrongPassword,
+    #[error("This datastore requires a keyfile to be unlocked, use --keyfile")]
+    KeyfileRequired,
+    #[error("Wrong keyfile for this datastore")]
+    WrongKeyfile,
+}
+
+/// Key derivation functions usable to derive a key from a password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    /// Argon2id hashing.
+    Argon2id,
+}
+
+impl KdfAlgorithm {
+    /// returns the identifier of the key derivation function in the vault header.
+    fn id(&self) -> u8 {
+        match self {
+            KdfAlgorithm::Argon2id => 1,
+        }
+    }
+
+    /// returns the key derivation function given its identifier in the vault header.
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(KdfAlgorithm::Argon2id),
+            _ => bail!(VaultFormatError::UnsupportedKdf(id)),
+        }
+    }
+}
+
+/// Possible kinds of key slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySlotKind {
+    /// The vault key is wrapped by a key derived from the master password, and
+    /// from a keyfile if its fingerprint is set.
+    MasterPassword {
+        keyfile_fingerprint: Option<[u8; KEYFILE_FINGERPRINT_LENGTH]>,
+    },
+}
+
+impl KeySlotKind {
+    /// Serializes the key slot kind (identifier and kind-specific data).
+    fn write(&self, bytes: &mut Vec<u8>) {
+        match self {
+            KeySlotKind::MasterPassword {
+                keyfile_fingerprint: None,
+            } => bytes.push(1),
+            KeySlotKind::MasterPassword {
+                keyfile_fingerprint: Some(fingerprint),
+            } => {
+                bytes.push(2);
+                bytes.extend_from_slice(fingerprint);
+            }
+        }
+    }
+
+    /// Parses a key slot kind (identifier and kind-specific data).
+    fn read(reader: &mut ByteReader) -> Result<Self> {
+        match reader.take_array::<1>()?[0] {
+            1 => Ok(KeySlotKind::MasterPassword {
+                keyfile_fingerprint: None,
+            }),
+            2 => Ok(KeySlotKind::MasterPassword {
+                keyfile_fingerprint: Some(reader.take_array()?),
+            }),
+            id => bail!(VaultFormatError::UnsupportedKeySlot(id)),
+        }
+    }
+}
+
+/// Key slot of a vault, holding the vault key wrapped by a secret.
+///
+/// Binary layout (integers are little-endian):
+/// ```text
+/// kind (1) | [keyfile fingerprint (16)] | kdf id (1) | mem cost (4) | time cost (4) | lanes (4) | salt (32) | nonce (24) | wrapped key (48)
+/// ```
+///
+/// The keyfile fingerprint is only present for key slots requiring a keyfile.
+///
+/// Everything before the wrapped key is authenticated as associated data of the wrapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySlot {
+    /// Kind of secret wrapping the vault key.
+    pub kind: KeySlotKind,
+    /// Key derivation function of the secret.
+    pub kdf: KdfAlgorithm,
+    /// Parameters of the key derivation function.
+    pub kdf_params: KdfParams,
+    /// Salt of the key derivation function.
+    pub salt: [u8; SALT_LENGTH],
+    /// Nonce of the vault key wrapping.
+    pub nonce: [u8; NONCE_LENGTH],
+    /// Vault key, ciphered by the key derived from the secret.
+    pub wrapped_key: [u8; KEY_LENGTH + TAG_LENGTH],
+}
+
+impl KeySlot {
+    /// Size of the binary key slot without keyfile fingerprint, in bytes.
+    const LENGTH: usize = 1 + 1 + 4 * 3 + SALT_LENGTH + NONCE_LENGTH + KEY_LENGTH + TAG_LENGTH;
+
+    /// Wraps a vault key with a master password (and a keyfile), with a fresh salt & nonce.
+    ///
+    /// This will return an error upon key derivation or encryption error.
+    pub fn wrap(
+        key: &VaultKey,
+        master_password: &str,
+        keyfile: Option<&Keyfile>,
+        kdf_params: KdfParams,
+    ) -> Result<Self> {
+        let mut slot = Self {
+            kind: KeySlotKind::MasterPassword {
+                keyfile_fingerprint: keyfile.map(Keyfile::fingerprint),
+            },
+            kdf: KdfAlgorithm::Argon2id,
+            kdf_params,
+            salt: crypto::random_bytes(),
+            nonce: crypto::random_bytes(),
+            wrapped_key: [0u8; KEY_LENGTH + TAG_LENGTH],
+        };
+
+        let secret = keyfile.map(Keyfile::secret).unwrap_or_default();
+
+        let wrapping_key =
+            crypto::derive_key(master_password, &slot.salt, &slot.kdf_params, secret)?;
+        let wrapped_key =
+            crypto::encrypt(key.expose(), &wrapping_key, &slot.nonce, &slot.metadata())?;
+
+        slot.wrapped_key = wrapped_key.as_slice().try_into()?;
+
+        Ok(slot)
+    }
+
+    /// Checks whether the key slot is unlocked by the master password.
+    pub fn is_master_password(&self) -> bool {
+        matches!(self.kind, KeySlotKind::MasterPassword { .. })
+    }
+
+    /// Unwraps the vault key with a master password (and a keyfile).
+    ///
+    /// This will return an error if :
+    /// - the keyfile is required but missing, or is not the right one.
+    /// - the key cannot be derived from the master password.
+    /// - the master password is wrong (or the key slot has been tampered with).
+    pub fn unwrap(&self, master_password: &str, keyfile: Option<&Keyfile>) -> Result<VaultKey> {
+        let KeySlotKind::MasterPassword {
+            keyfile_fingerprint,
+        } = self.kind;
+
+        let secret = match (keyfile_fingerprint, keyfile) {
+            (None, _) => &[][..],
+            (Some(_), None) => bail!(VaultFormatError::KeyfileRequired),
+            (Some(fingerprint), Some(keyfile)) if fingerprint != keyfile.fingerprint() => {
+                bail!(VaultFormatError::WrongKeyfile)
+            }
+            (Some(_), Some(keyfile)) => keyfile.secret(),
+        };
+
+        let wrapping_key =
+            crypto::derive_key(master_password, &self.salt, &self.kdf_params, secret)?;
+
+        let Ok(key) = crypto::decrypt(
+            &self.wrapped_key,
+            &wrapping_key,
+            &self.nonce,
+            &self.metadata(),
+        ) else {
+            bail!(VaultFormatError::WrongPassword);
+        };
+
+        match SecretKey::from_slice(&key) {
+            Some(key) => Ok(key),
+            None => bail!(VaultFormatError::Truncated),
+        }
+    }
+
+    /// Serializes the key slot into its binary layout.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.metadata();
+        bytes.extend_from_slice(&self.wrapped_key);
+
+        bytes
+    }
+
+    /// Serializes the authenticated metadata of the key slot.
+    fn metadata(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::LENGTH + KEYFILE_FINGERPRINT_LENGTH);
+
+        self.kind.write(&mut bytes);
+        bytes.push(self.kdf.id());
+        write_kdf_params(&mut bytes, &self.kdf_params);
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.nonce);
+
+        bytes
+    }
+
+    /// Parses a key slot.
+    fn read(reader: &mut ByteReader) -> Result<Self> {
+        Ok(Self {
+            kind: KeySlotKind::read(reader)?,
+            kdf: KdfAlgorithm::from_id(reader.take_array::<1>()?[0])?,
+            kdf_params: read_kdf_params(reader)?,
+            salt: reader.take_array()?,
+            nonce: reader.take_array()?,
+            wrapped_key: reader.take_array()?,
+        })
+    }
+}
+
+/// Header of a vault file.
+///
+/// The whole header is authenticated as associated data of the ciphertext, so
+/// that any tampering (e.g. with the key slots) is detected.
+///
+/// Binary layout (integers are little-endian):
+/// ```text
+/// magic (8) | version (2) | nonce (24) | key slots count (1) | key slots (see `KeySlot`)
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultHeader {
+    /// Version of the vault file format.
+    pub version: u16,
+    /// Nonce of the content cipher.
+    pub nonce: [u8; NONCE_LENGTH],
+    /// Key slots holding the wrapped vault key.
+    pub slots: Vec<KeySlot>,
+}
+
+impl VaultHeader {
+    /// Creates a header for the current format version, with a fresh nonce.
+    pub fn new(slots: Vec<KeySlot>) -> Self {
+        Self {
+            version: VAULT_FORMAT_VERSION,
+            nonce: crypto::random_bytes(),
+            slots,
+        }
+    }
+
+    /// Serializes the header into its binary layout.
+    ///
+    /// This will return an error if there are more than `MAX_KEY_SLOTS` key slots.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let Some(slots_count) = u8::try_from(self.slots.len())
+            .ok()
+            .filter(|count| *count <= MAX_KEY_SLOTS)
+        else {
+            bail!(VaultFormatError::TooManyKeySlots);
+        };
+
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(VAULT_MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.nonce);
+        bytes.push(slots_count);
+
+        for slot in self.slots.iter() {
+            bytes.extend_from_slice(&slot.to_bytes());
+        }
+
+        Ok(bytes)
+    }
+
+    /// Parses a header from the start of a vault file in the current format.
+    ///
+    /// returns the header and its size, in bytes.
+    ///
+    /// This will return an error if :
+    /// - the content does not start with the vault magic number.
+    /// - the header is truncated.
+    /// - the format version, a key slot kind or a key derivation function are not supported.
+    pub fn from_bytes(content: &[u8]) -> Result<(Self, usize)> {
+        let mut reader = ByteReader::new(content);
+
+        let version = read_version(&mut reader)?;
+
+        // version 2 is a subset of the current version
+        if !(2..=VAULT_FORMAT_VERSION).contains(&version) {
+            bail!(VaultFormatError::UnsupportedVersion(version));
+        }
+
+        let nonce = reader.take_array()?;
+        let slots_count = reader.take_array::<1>()?[0];
+
+        if slots_count > MAX_KEY_SLOTS {
+            bail!(VaultFormatError::TooManyKeySlots);
+        }
+
+        let mut slots = vec![];
+
+        for _ in 0..slots_count {
+            slots.push(KeySlot::read(&mut reader)?);
+        }
+
+        let header = Self {
+            version,
+            nonce,
+            slots,
+        };
+
+        Ok((header, reader.position()))
+    }
+}
+
+/// Representation of a vault file in the legacy format (version `0`).
+#[derive(Default, Debug, Serialize, Deserialize)]
+struct LegacyMessage {
+    /// Cryptographic nonce.
+    nonce: [u8; NONCE_LENGTH],
+    /// Password salt.
+    salt: [u8; SALT_LENGTH],
+    /// Crypted message.
+    message: Vec<u8>,
+}
+
+/// Content of a vault file once opened.
+#[derive(Debug)]
+pub struct OpenedVault {
+    /// Deciphered content of the vault, wiped from memory upon drop.
+    pub content: Zeroizing<Vec<u8>>,
+    /// Key of the vault.
+    pub key: VaultKey,
+    /// Key slots of the vault.
+    pub slots: Vec<KeySlot>,
+    /// Version of the vault file format.
+    pub version: u16,
+}
+
+/// Creates the key of a new vault, and its master password key slot.
+///
+/// This will return an error upon key derivation or encryption error.
+pub fn create_key(
+    master_password: &str,
+    keyfile: Option<&Keyfile>,
+    kdf_params: KdfParams,
+) -> Result<(VaultKey, KeySlot)> {
+    let key = VaultKey::random();
+    let slot = KeySlot::wrap(&key, master_password, keyfile, kdf_params)?;
+
+    Ok((key, slot))
+}
+
+/// Ciphers a content into a vault file in the current format, with a vault key.
+///
+/// No key derivation happens here, the key slots are only copied in the header.
+///
+/// This will return an error upon encryption error.
+pub fn seal(content: &[u8], key: &VaultKey, slots: &[KeySlot]) -> Result<Vec<u8>> {
+    let header = VaultHeader::new(slots.to_vec());
+    let header_bytes = header.to_bytes()?;
+
+    let ciphertext = crypto::encrypt(content, key, &header.nonce, &header_bytes)?;
+
+    let mut vault = header_bytes;
+    vault.extend_from_slice(&ciphertext);
+
+    Ok(vault)
+}
+
+/// Deciphers a vault file in the current format with its vault key.
+///
+/// This will return an error if the vault file cannot be parsed or deciphered.
+pub fn open_with_key(vault: &[u8], key: &VaultKey) -> Result<Zeroizing<Vec<u8>>> {
+    let (header, header_length) = VaultHeader::from_bytes(vault)?;
+    let (header_bytes, ciphertext) = vault.split_at(header_length);
+
+    crypto::decrypt(ciphertext, key, &header.nonce, header_bytes)
+}
+
+/// Deciphers a vault file with a master password, in any supported format version.
+///
+/// Vault files in an older format are given a fresh vault key and key slot, and
+/// are migrated to the current format on their next `seal`.
+///
+/// This will return an error if :
+/// - the vault file format is not supported.
+/// - the master password (and keyfile) does not unlock any key slot.
+/// - the vault file cannot be deciphered (e.g. tampered file).
+pub fn open(vault: &[u8], master_password: &str, keyfile: Option<&Keyfile>) -> Result<OpenedVault> {
+    if vault.is_empty() {
+        bail!(VaultFormatError::Empty);
+    }
+
+    if !vault.starts_with(VAULT_MAGIC) {
+        return open_legacy(vault, master_password);
+    }
+
+    let version = read_version(&mut ByteReader::new(vault))?;
+
+    if version == 1 {
+        return open_v1(vault, master_password);
+    }
+
+    let (header, _) = VaultHeader::from_bytes(vault)?;
+
+    let mut keyfile_error = None;
+    let mut key = None;
+
+    for slot in header.slots.iter().filter(|slot| slot.is_master_password()) {
+        match slot.unwrap(master_password, keyfile) {
+            Ok(unwrapped) => {
+                key = Some(unwrapped);
+                break;
+            }
+            Err(error) => {
+                let is_keyfile_error = matches!(
+                    error.downcast_ref::<VaultFormatError>(),
+                    Some(VaultFormatError::KeyfileRequired | VaultFormatError::WrongKeyfile)
+                );
+
+                if is_keyfile_error {
+                    keyfile_error.get_or_insert(error);
+                }
+            }
+        }
+    }
+
+    let Some(key) = key else {
+        // a keyfile issue is more relevant than a wrong master password
+        bail!(keyfile_error.unwrap_or_else(|| VaultFormatError::WrongPassword.into()));
+    };
+
+    Ok(OpenedVault {
+        content: open_with_key(vault, &key)?,
+        key,
+        slots: header.slots,
+        version,
+    })
+}
+
+/// Deciphers a vault file in the format version `1`.
+///
+/// Binary layout (integers are little-endian):
+/// ```text
+/// magic (8) | version (2) | kdf id (1) | mem cost (4) | time cost (4) | lanes (4) | salt (32) | nonce (24)
+/// ```
+fn open_v1(vault: &[u8], master_password: &str) -> Result<OpenedVault> {
+    let mut reader = ByteReader::new(vault);
+
+    let version = read_version(&mut reader)?;
+    KdfAlgorithm::from_id(reader.take_array::<1>()?[0])?;
+    let kdf_params = read_kdf_params(&mut reader)?;
+    let salt: [u8; SALT_LENGTH] = reader.take_array()?;
+    let nonce: [u8; NONCE_LENGTH] = reader.take_array()?;
+
+    let (header_bytes, ciphertext) = vault.split_at(reader.position());
+
+    let key = crypto::derive_key(master_password, &salt, &kdf_params, &[])?;
+    let content = crypto::decrypt(ciphertext, &key, &nonce, header_bytes)?;
+
+    migrated(content, master_password, kdf_params, version)
+}
+
+/// Deciphers a vault file in the legacy format (version `0`).
+fn open_legacy(vault: &[u8], master_password: &str) -> Result<OpenedVault> {
+    let mut buffer = Vec::<u8>::new();
+
+    if general_purpose::STANDARD
+        .decode_vec(vault, &mut buffer)
+        .is_err()
+    {
+        bail!(VaultFormatError::UnknownFormat);
+    }
+
+    let Ok(message) = serde_json::from_slice::<LegacyMessage>(&buffer) else {
+        bail!(VaultFormatError::UnknownFormat);
+    };
+
+    // legacy vaults were always ciphered with the default parameters
+    let kdf_params = KdfParams::default();
+
+    let key = crypto::derive_key(master_password, &message.salt, &kdf_params, &[])?;
+    let content = crypto::decrypt(&message.message, &key, &message.nonce, &[])?;
+
+    migrated(content, master_password, kdf_params, 0)
+}
+
+/// Creates a vault key and key slot for the content of a vault in an older format.
+///
+/// Vaults in an older format never require a keyfile.
+fn migrated(
+    content: Zeroizing<Vec<u8>>,
+    master_password: &str,
+    kdf_params: KdfParams,
+    version: u16,
+) -> Result<OpenedVault> {
+    let (key, slot) = create_key(master_password, None, kdf_params)?;
+
+    Ok(OpenedVault {
+        content,
+        key,
+        slots: vec![slot],
+        version,
+    })
+}
+
+/// Reads the magic number and the format version of a vault file.
+fn read_version(reader: &mut ByteReader) -> Result<u16> {
+    if reader.take(VAULT_MAGIC.len())? != VAULT_MAGIC {
+        bail!(VaultFormatError::UnknownFormat);
+    }
+
+    Ok(u16::from_le_bytes(reader.take_array()?))
+}
+
+/// Serializes key derivation parameters.
+fn write_kdf_params(bytes: &mut Vec<u8>, kdf_params: &KdfParams) {
+    bytes.extend_from_slice(&kdf_params.mem_cost.to_le_bytes());
+    bytes.extend_from_slice(&kdf_params.time_cost.to_le_bytes());
+    bytes.extend_from_slice(&kdf_params.lanes.to_le_bytes());
+}
+
+/// Parses key derivation parameters.
+///
+/// This will return an error if the parameters exceed `KdfParams::MAXIMUM`.
+fn read_kdf_params(reader: &mut ByteReader) -> Result<KdfParams> {
+    let kdf_params = KdfParams {
+        mem_cost: u32::from_le_bytes(reader.take_array()?),
+        time_cost: u32::from_le_bytes(reader.take_array()?),
+        lanes: u32::from_le_bytes(reader.take_array()?),
+    };
+
+    if kdf_params.exceeds(&KdfParams::MAXIMUM) {
+        bail!(VaultFormatError::KdfParamsTooHigh(kdf_params));
+    }
+
+    Ok(kdf_params)
+}
+
+/// Sequential reader over a byte slice.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    /// creates a reader at the start of a byte slice.
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// returns the number of bytes read so far.
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    /// reads the next `len` bytes.
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < self.position + len {
+            bail!(VaultFormatError::Truncated);
+        }
+
+        let taken = &self.bytes[self.position..self.position + len];
+        self.position += len;
+
+        Ok(taken)
+    }
+
+    /// reads the next `N` bytes as an array.
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+}
+
+// unit tests for this module.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFolder;
+
+    /// weak key derivation parameters, to keep tests fast
+    const TEST_KDF_PARAMS: KdfParams = KdfParams {
+        mem_cost: 8,
+        time_cost: 1,
+        lanes: 1,
+    };
+
+    /// test function for header serialization
+    #[test]
+    fn assert_header_roundtrip() {
+        let (_, slot) = create_key("password", None, TEST_KDF_PARAMS).unwrap();
+        let header = VaultHeader::new(vec![slot.clone(), slot]);
+        let bytes = header.to_bytes().unwrap();
+
+        let (parsed, length) = VaultHeader::from_bytes(&bytes).unwrap();
+
+        assert_eq!(header, parsed);
+        assert_eq!(bytes.len(), length);
+        assert_eq!(VAULT_MAGIC.len() + 2 + 24 + 1 + 2 * KeySlot::LENGTH, length);
+        assert!(VaultHeader::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    /// test function for vault sealing & opening
+    #[test]
+    fn assert_vault_roundtrip() {
+        let (key, slot) = create_key("password", None, TEST_KDF_PARAMS).unwrap();
+        let vault = seal(b"content", &key, std::slice::from_ref(&slot)).unwrap();
+
+        let opened = open(&vault, "password", None).unwrap();
+
+        assert_eq!(b"content".to_vec(), *opened.content);
+        assert_eq!(key, opened.key);
+        assert_eq!(vec![slot], opened.slots);
+        assert_eq!(VAULT_FORMAT_VERSION, opened.version);
+        assert!(open(&vault, "wrong password", None).is_err());
+    }
+
+    /// test function for vaults with several key slots
+    #[test]
+    fn assert_several_key_slots() {
+        let (key, first) = create_key("first", None, TEST_KDF_PARAMS).unwrap();
+        let second = KeySlot::wrap(&key, "second", None, TEST_KDF_PARAMS).unwrap();
+        let vault = seal(b"content", &key, &[first, second]).unwrap();
+
+        assert_eq!(
+            b"content".to_vec(),
+            *open(&vault, "first", None).unwrap().content
+        );
+        assert_eq!(
+            b"content".to_vec(),
+            *open(&vault, "second", None).unwrap().content
+        );
+        assert!(open(&vault, "third", None).is_err());
+    }
+
+    /// test function for key slots requiring a keyfile
+    #[test]
+    fn assert_keyfile_key_slot() {
+        let folder = TempFolder::new("keyfile");
+
+        let keyfile = Keyfile::generate(&folder.join("keyfile")).unwrap();
+        let other_keyfile = Keyfile::generate(&folder.join("other")).unwrap();
+
+        let (key, slot) = create_key("password", Some(&keyfile), TEST_KDF_PARAMS).unwrap();
+        let vault = seal(b"content", &key, &[slot]).unwrap();
+
+        let opened = open(&vault, "password", Some(&keyfile)).unwrap();
+        assert_eq!(b"content".to_vec(), *opened.content);
+
+        let error_of = |keyfile: Option<&Keyfile>, password: &str| {
+            open(&vault, password, keyfile)
+                .unwrap_err()
+                .downcast::<VaultFormatError>()
+                .unwrap()
+        };
+
+        assert!(matches!(
+            error_of(None, "password"),
+            VaultFormatError::KeyfileRequired
+        ));
+        assert!(matches!(
+            error_of(Some(&other_keyfile), "password"),
+            VaultFormatError::WrongKeyfile
+        ));
+        assert!(matches!(
+            error_of(Some(&keyfile), "wrong password"),
+            VaultFormatError::WrongPassword
+        ));
+        assert!(Keyfile::generate(&folder.join("keyfile")).is_err());
+    }
+
+    /// test function for header authentication
+    #[test]
+    fn assert_tampered_header_is_rejected() {
+        let (key, slot) = create_key("password", None, TEST_KDF_PARAMS).unwrap();
+        let vault = seal(b"content", &key, &[slot]).unwrap();
+
+        // last byte of the content nonce
+        let mut tampered = vault.clone();
+        tampered[VAULT_MAGIC.len() + 2 + NONCE_LENGTH - 1] ^= 1;
+        assert!(open(&tampered, "password", None).is_err());
+
+        // time cost of the key slot
+        let mut tampered = vault.clone();
+        tampered[VAULT_MAGIC.len() + 2 + NONCE_LENGTH + 1 + 6] ^= 1;
+        assert!(open(&tampered, "password", None).is_err());
+
+        // unsupported format version
+        let mut tampered = vault;
+        tampered[VAULT_MAGIC.len()] = 0xff;
+        assert!(open(&tampered, "password", None).is_err());
+    }
+
+    /// test function for headers asking for too much key derivation work
+    #[test]
+    fn assert_oversized_header_is_rejected() {
+        let (key, slot) = create_key("password", None, TEST_KDF_PARAMS).unwrap();
+        let vault = seal(b"content", &key, std::slice::from_ref(&slot)).unwrap();
+        let slots_count_index = VAULT_MAGIC.len() + 2 + NONCE_LENGTH;
+
+        // memory cost of the key slot (after its kind & kdf ids)
+        let mut oversized = vault.clone();
+        oversized[slots_count_index + 1 + 2..slots_count_index + 1 + 6]
+            .copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            open(&oversized, "password", None)
+                .unwrap_err()
+                .downcast::<VaultFormatError>()
+                .unwrap(),
+            VaultFormatError::KdfParamsTooHigh(_)
+        ));
+
+        // too many key slots
+        let mut oversized = vault;
+        oversized[slots_count_index] = MAX_KEY_SLOTS + 1;
+
+        assert!(matches!(
+            open(&oversized, "password", None)
+                .unwrap_err()
+                .downcast::<VaultFormatError>()
+                .unwrap(),
+            VaultFormatError::TooManyKeySlots
+        ));
+        assert!(VaultHeader::new(vec![slot; MAX_KEY_SLOTS as usize + 1])
+            .to_bytes()
+            .is_err());
+    }
+
+    /// test function for the migration of vaults in the format version 1
+    #[test]
+    fn assert_v1_vault_is_opened() {
+        let salt: [u8; SALT_LENGTH] = crypto::random_bytes();
+        let nonce: [u8; NONCE_LENGTH] = crypto::random_bytes();
+
+        let mut header = VAULT_MAGIC.to_vec();
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.push(KdfAlgorithm::Argon2id.id());
+        write_kdf_params(&mut header, &TEST_KDF_PARAMS);
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&nonce);
+
+        let key = crypto::derive_key("password", &salt, &TEST_KDF_PARAMS, &[]).unwrap();
+        let mut vault = header.clone();
+        vault.extend_from_slice(&crypto::encrypt(b"content", &key, &nonce, &header).unwrap());
+
+        let opened = open(&vault, "password", None).unwrap();
+
+        assert_eq!(b"content".to_vec(), *opened.content);
+        assert_eq!(1, opened.version);
+        assert_eq!(TEST_KDF_PARAMS, opened.slots[0].kdf_params);
+        assert!(open(&vault, "wrong password", None).is_err());
+    }
+
+    /// test function for the migration of legacy vaults
+    #[test]
+    fn assert_legacy_vault_is_opened() {
+        let salt = crypto::random_bytes();
+        let nonce = crypto::random_bytes();
+        let key = crypto::derive_key("password", &salt, &KdfParams::default(), &[]).unwrap();
+
+        let message = LegacyMessage {
+            nonce,
+            salt,
+            message: crypto::encrypt(b"content", &key, &nonce, &[]).unwrap(),
+        };
+
+        let mut legacy = String::new();
+        general_purpose::STANDARD
+            .encode_string(serde_json::to_string(&message).unwrap(), &mut legacy);
+
+        let opened = open(legacy.as_bytes(), "password", None).unwrap();
+
+        assert_eq!(b"content".to_vec(), *opened.content);
+        assert_eq!(0, opened.version);
+    }
+}